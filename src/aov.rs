@@ -0,0 +1,90 @@
+use crate::*;
+
+// Arbitrary output variables: data about the surfaces seen through each pixel,
+// written next to the rendered image for denoisers and compositing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    Depth,      // distance along the camera ray, infinite where nothing is hit
+    Normal,     // world space shading normal
+    Albedo,     // material color
+    Position,   // world space position
+    ObjectId,   // see Shape::assign_ids, 0 where nothing is hit
+    MaterialId, // likewise
+}
+
+impl Aov {
+    pub const NAMES: [&'static str; 6] = [
+        "depth",
+        "normal",
+        "albedo",
+        "position",
+        "object_id",
+        "material_id",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "depth" => Some(Aov::Depth),
+            "normal" => Some(Aov::Normal),
+            "albedo" => Some(Aov::Albedo),
+            "position" => Some(Aov::Position),
+            "object_id" => Some(Aov::ObjectId),
+            "material_id" => Some(Aov::MaterialId),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+
+    // names of the channels in exr files, one per used component of `value`
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+        }
+    }
+
+    pub fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+
+    // albedo and normal are averaged over the samples of a pixel like the image,
+    // the others come from its first sample so that they do not blend across edges
+    pub fn is_averaged(&self) -> bool {
+        matches!(self, Aov::Normal | Aov::Albedo)
+    }
+
+    // the value at the first hit of a camera ray, if any
+    pub fn value(&self, hit: Option<&Intersection>) -> [f32; 3] {
+        let info = match hit {
+            Some(info) => info,
+            None if *self == Aov::Depth => return [f32::INFINITY; 3],
+            None => return [0.0; 3],
+        };
+        match self {
+            Aov::Depth => [info.t; 3],
+            Aov::Normal => {
+                let n = info.normal.normalize();
+                [n.x, n.y, n.z]
+            }
+            Aov::Albedo => {
+                let c = info.material.color(info);
+                [c.r, c.g, c.b]
+            }
+            Aov::Position => [info.pos.x, info.pos.y, info.pos.z],
+            Aov::ObjectId => [info.object_id as f32; 3],
+            Aov::MaterialId => [info.material_id as f32; 3],
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use rand::Rng;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::*;
+
+// radiance seen by rays that leave the scene
+#[derive(Clone, Debug)]
+pub enum Background {
+    Solid {
+        color: Color,
+    },
+    Gradient {
+        horizon: Color,
+        zenith: Color,
+        ground: Color, // below the horizon
+    },
+    // Preetham et al. clear sky, normalized to `intensity` at the zenith;
+    // the sun itself is not included and should be added as a directional light
+    Sky {
+        sun_direction: Vec3,
+        turbidity: f32,
+        intensity: f32,
+    },
+    // equirectangular image, the center of the image looking down -z
+    Environment {
+        map: Arc<EnvironmentMap>,
+        rotation: f32, // degrees around the y axis
+        intensity: f32,
+    },
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Solid {
+            color: Color::black(),
+        }
+    }
+}
+
+impl Background {
+    pub fn is_black(&self) -> bool {
+        match self {
+            Background::Solid { color } => *color == Color::black(),
+            _ => false,
+        }
+    }
+
+    // dir must be normalized
+    pub fn radiance(&self, dir: Vec3) -> Color {
+        match self {
+            Background::Solid { color } => *color,
+            Background::Gradient {
+                horizon,
+                zenith,
+                ground,
+            } => {
+                if dir.y < 0.0 {
+                    *ground
+                } else {
+                    (1.0 - dir.y) * *horizon + dir.y * *zenith
+                }
+            }
+            Background::Sky {
+                sun_direction,
+                turbidity,
+                intensity,
+            } => preetham_sky(dir, sun_direction.normalize(), *turbidity) * *intensity,
+            Background::Environment {
+                map,
+                rotation,
+                intensity,
+            } => map.lookup(rotate_y(dir, -rotation.to_radians())) * *intensity,
+        }
+    }
+
+    // a direction toward the background with its radiance and solid angle pdf,
+    // for backgrounds that are worth importance sampling
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<(Vec3, Color, f32)> {
+        match self {
+            Background::Environment {
+                map,
+                rotation,
+                intensity,
+            } => {
+                let (dir, radiance, pdf) = map.sample(rng)?;
+                Some((
+                    rotate_y(dir, rotation.to_radians()),
+                    radiance * *intensity,
+                    pdf,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    // solid angle pdf of `sample` choosing dir, 0 if the background is not sampled
+    pub fn pdf(&self, dir: Vec3) -> f32 {
+        match self {
+            Background::Environment { map, rotation, .. } => {
+                map.pdf(rotate_y(dir, -rotation.to_radians()))
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+fn rotate_y(dir: Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos * dir.x + sin * dir.z, dir.y, -sin * dir.x + cos * dir.z)
+}
+
+// linear radiance of an equirectangular HDR image, with the distribution of
+// its pixels by luminance for importance sampling
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    pub path: String,
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    row_cdf: Vec<f32>,    // running sum of the row weights
+    column_cdf: Vec<f32>, // running sum of the pixel weights within each row
+}
+
+impl EnvironmentMap {
+    pub fn open(path: &str) -> Result<EnvironmentMap, String> {
+        let (width, height, pixels) =
+            read_linear_image(path, true).map_err(|e| format!("{}: {}", path, e))?;
+
+        // rows near the poles cover less solid angle
+        let mut row_cdf = Vec::with_capacity(height);
+        let mut column_cdf = Vec::with_capacity(width * height);
+        let mut total = 0.0;
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            let mut row = 0.0;
+            for x in 0..width {
+                row += pixels[y * width + x].luminance() * sin_theta;
+                column_cdf.push(row);
+            }
+            total += row;
+            row_cdf.push(total);
+        }
+
+        Ok(EnvironmentMap {
+            path: path.to_string(),
+            width,
+            height,
+            pixels,
+            row_cdf,
+            column_cdf,
+        })
+    }
+
+    fn lookup(&self, dir: Vec3) -> Color {
+        let (x, y) = self.pixel(dir);
+        self.pixels[y * self.width + x]
+    }
+
+    fn pixel(&self, dir: Vec3) -> (usize, usize) {
+        let u = 0.5 + dir.x.atan2(-dir.z) / (2.0 * PI);
+        let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> Option<(Vec3, Color, f32)> {
+        let total = *self.row_cdf.last()?;
+        if total <= 0.0 {
+            return None;
+        }
+        let target = rng.gen::<f32>() * total;
+        let y = self
+            .row_cdf
+            .partition_point(|&w| w < target)
+            .min(self.height - 1);
+        let row = &self.column_cdf[y * self.width..(y + 1) * self.width];
+        let target = rng.gen::<f32>() * row[self.width - 1];
+        let x = row.partition_point(|&w| w < target).min(self.width - 1);
+
+        // uniform within the pixel
+        let u = (x as f32 + rng.gen::<f32>()) / self.width as f32;
+        let v = (y as f32 + rng.gen::<f32>()) / self.height as f32;
+        let (sin_phi, cos_phi) = ((u - 0.5) * 2.0 * PI).sin_cos();
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        let dir = Vec3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi);
+
+        let pdf = self.pdf(dir);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((dir, self.pixels[y * self.width + x], pdf))
+    }
+
+    fn pdf(&self, dir: Vec3) -> f32 {
+        let total = match self.row_cdf.last() {
+            Some(&total) if total > 0.0 => total,
+            _ => return 0.0,
+        };
+        let sin_dir = (1.0 - dir.y * dir.y).max(0.0).sqrt();
+        if sin_dir <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.pixel(dir);
+        let sin_theta = (PI * (y as f32 + 0.5) / self.height as f32).sin();
+        let weight = self.pixels[y * self.width + x].luminance() * sin_theta;
+
+        // uniform within the pixel's rectangle of the image, which is stretched
+        // over 2 * pi * pi * sin(theta) steradians per unit area
+        let area = (self.width * self.height) as f32;
+        weight / total * area / (2.0 * PI * PI * sin_dir)
+    }
+}
+
+// Perez distribution F(theta, gamma) with coefficients (A, B, C, D, E)
+fn perez(theta: f32, gamma: f32, c: [f32; 5]) -> f32 {
+    (1.0 + c[0] * (c[1] / theta.cos()).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+fn preetham_sky(dir: Vec3, sun: Vec3, t: f32) -> Color {
+    // below the horizon the sky color at the horizon continues
+    let theta = dir.y.clamp(0.001, 1.0).acos();
+    let theta_s = sun.y.clamp(0.0, 1.0).acos();
+    let horizontal = Vec3::new(dir.x, dir.y.max(0.001), dir.z).normalize();
+    let gamma = horizontal.dot(sun).clamp(-1.0, 1.0).acos();
+
+    let coeff_y = [
+        0.1787 * t - 1.4630,
+        -0.3554 * t + 0.4275,
+        -0.0227 * t + 5.3251,
+        0.1206 * t - 2.5771,
+        -0.0670 * t + 0.3703,
+    ];
+    let coeff_x = [
+        -0.0193 * t - 0.2592,
+        -0.0665 * t + 0.0008,
+        -0.0004 * t + 0.2125,
+        -0.0641 * t - 0.8989,
+        -0.0033 * t + 0.0452,
+    ];
+    let coeff_y2 = [
+        -0.0167 * t - 0.2608,
+        -0.0950 * t + 0.0092,
+        -0.0079 * t + 0.2102,
+        -0.0441 * t - 1.6537,
+        -0.0109 * t + 0.0529,
+    ];
+
+    let (s1, s2, s3) = (theta_s, theta_s.powi(2), theta_s.powi(3));
+    let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s1)
+        + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s1 + 0.00394)
+        + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s1 + 0.25886);
+    let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s1)
+        + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s1 + 0.00516)
+        + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s1 + 0.26688);
+
+    // luminance relative to the zenith
+    let lum = perez(theta, gamma, coeff_y) / perez(0.0, theta_s, coeff_y);
+    let x = zenith_x * perez(theta, gamma, coeff_x) / perez(0.0, theta_s, coeff_x);
+    let y = zenith_y * perez(theta, gamma, coeff_y2) / perez(0.0, theta_s, coeff_y2);
+
+    // xyY to linear sRGB
+    let cx = x / y * lum;
+    let cz = (1.0 - x - y) / y * lum;
+    Color::new(
+        (3.2406 * cx - 1.5372 * lum - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * lum + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * lum + 1.0570 * cz).max(0.0),
+    )
+}
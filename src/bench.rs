@@ -0,0 +1,45 @@
+use crate::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::time::Instant;
+
+const BENCH_ROUNDS: u32 = 4;
+
+// casts one primary ray per pixel against each built-in env and reports the throughput
+pub fn run_benchmark(options: &Options) {
+    for (name, env) in [("default", Env::Default), ("teapot", Env::Teapot)] {
+        let scene = make_env(env);
+        let shape = &scene.root;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = Instant::now();
+        let mut hit_count: u64 = 0;
+        for _ in 0..BENCH_ROUNDS {
+            for w in 0..options.width {
+                for h in 0..options.height {
+                    let ray = scene.camera.generate_ray(
+                        w as f32 + 0.5,
+                        h as f32 + 0.5,
+                        options.width,
+                        options.height,
+                        &mut rng,
+                    );
+                    if shape.intersect(ray).is_some() {
+                        hit_count += 1;
+                    }
+                }
+            }
+        }
+        let duration = start.elapsed();
+
+        let ray_count = BENCH_ROUNDS as u64 * options.width as u64 * options.height as u64;
+        println!(
+            "{}: {} rays, {} hits, {:?} ({:.3} Mrays/s)",
+            name,
+            ray_count,
+            hit_count,
+            duration,
+            ray_count as f64 / duration.as_secs_f64() / 1e6
+        );
+    }
+}
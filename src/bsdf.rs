@@ -0,0 +1,361 @@
+use rand::Rng;
+use std::f32::consts::PI;
+
+use crate::*;
+
+// Scattering at a surface hit. Mirrors, smooth glass and emitters are traced
+// by the integrators directly. Directions point away from the surface and n is
+// the normal on the side of wo.
+#[derive(Clone, Copy, Debug)]
+pub enum Bsdf {
+    Diffuse {
+        color: Color,
+    },
+    // glTF metallic-roughness: a diffuse base under GGX specular reflection,
+    // tinted by the base color as metallic goes to 1, optionally under a
+    // clear dielectric coat
+    Pbr {
+        color: Color,
+        metallic: f32,
+        roughness: f32, // perceptual, squared into the GGX alpha
+        specular: f32,  // 0.5 is a reflectance of 4% at normal incidence
+        clearcoat: f32, // strength of the coat, 0 for none
+        clearcoat_roughness: f32,
+    },
+    // metal with a complex index of refraction eta + ik per channel
+    Conductor {
+        eta: Color,
+        k: Color,
+        roughness: f32,
+    },
+    // rough glass, reflecting and transmitting through GGX microfacets
+    Dielectric {
+        color: Color, // tints the transmission
+        eta: f32,     // n_incident / n_transmitted on the side of wo
+        roughness: f32,
+    },
+}
+
+pub struct BsdfSample {
+    pub dir: Vec3,
+    pub weight: Color, // bsdf * cos / pdf
+    pub pdf: f32,      // solid angle
+}
+
+const CLEARCOAT_IOR: f32 = 1.5;
+
+impl Bsdf {
+    // whether light passes through the surface, to directions below n
+    pub fn is_transmissive(&self) -> bool {
+        matches!(self, Bsdf::Dielectric { .. })
+    }
+
+    // bsdf * |cos(n, wi)|
+    pub fn eval(&self, n: Vec3, wo: Vec3, wi: Vec3) -> Color {
+        let (cos_o, cos_i) = (n.dot(wo), n.dot(wi));
+        if cos_o <= 0.0 || (cos_i <= 0.0 && !self.is_transmissive()) {
+            return Color::black();
+        }
+        match *self {
+            Bsdf::Diffuse { color } => color * (cos_i / PI),
+            Bsdf::Pbr {
+                color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+            } => {
+                let h = (wo + wi).normalize();
+                let fresnel = fresnel_schlick(pbr_f0(color, metallic, specular), wo.dot(h));
+                let diffuse = (Color::white() - fresnel) * color * ((1.0 - metallic) / PI);
+                let specular = fresnel * microfacet_reflection(n, wo, wi, ggx_alpha(roughness));
+                let base = (diffuse + specular) * cos_i;
+                if clearcoat <= 0.0 {
+                    return base;
+                }
+
+                let coat_fresnel = clearcoat * fresnel_dielectric(wo.dot(h), 1.0 / CLEARCOAT_IOR);
+                let coat = coat_fresnel
+                    * microfacet_reflection(n, wo, wi, ggx_alpha(clearcoat_roughness))
+                    * cos_i;
+                (1.0 - coat_fresnel) * base + coat * Color::white()
+            }
+            Bsdf::Conductor { eta, k, roughness } => {
+                let h = (wo + wi).normalize();
+                fresnel_conductor(wo.dot(h), eta, k)
+                    * (microfacet_reflection(n, wo, wi, ggx_alpha(roughness)) * cos_i)
+            }
+            Bsdf::Dielectric {
+                color,
+                eta,
+                roughness,
+            } => {
+                let alpha = ggx_alpha(roughness);
+                if cos_i > 0.0 {
+                    let h = (wo + wi).normalize();
+                    let fresnel = fresnel_dielectric(wo.dot(h), eta);
+                    return Color::white()
+                        * (fresnel * microfacet_reflection(n, wo, wi, alpha) * cos_i);
+                }
+                let h = match refraction_half_vector(n, wo, wi, eta) {
+                    Some(h) => h,
+                    None => return Color::black(),
+                };
+                let (o_h, i_h) = (wo.dot(h), wi.dot(h));
+                let denom = eta * o_h + i_h;
+                let fresnel = fresnel_dielectric(o_h, eta);
+                let value = (1.0 - fresnel)
+                    * ggx_d(n.dot(h), alpha)
+                    * ggx_g2(cos_o, cos_i, alpha)
+                    * o_h
+                    * -i_h
+                    / (cos_o * denom * denom);
+                color * value
+            }
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, n: Vec3, wo: Vec3, rng: &mut R) -> Option<BsdfSample> {
+        let dir = match *self {
+            Bsdf::Diffuse { color } => {
+                // cos / pdf cancels to the albedo
+                let dir = to_world(sample_cosine_hemisphere(rng.gen(), rng.gen()), n);
+                let pdf = n.dot(dir) / PI;
+                if pdf <= 0.0 {
+                    return None;
+                }
+                return Some(BsdfSample {
+                    dir,
+                    weight: color,
+                    pdf,
+                });
+            }
+            Bsdf::Pbr {
+                color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+            } => {
+                let p_coat = clearcoat_probability(clearcoat);
+                let p_specular = pbr_specular_probability(color, metallic, specular);
+                let u = rng.gen::<f32>();
+                if u < p_coat {
+                    let alpha = ggx_alpha(clearcoat_roughness);
+                    reflect(
+                        -1.0 * wo,
+                        sample_ggx_visible(n, wo, alpha, rng.gen(), rng.gen()),
+                    )
+                } else if u < p_coat + (1.0 - p_coat) * p_specular {
+                    let alpha = ggx_alpha(roughness);
+                    reflect(
+                        -1.0 * wo,
+                        sample_ggx_visible(n, wo, alpha, rng.gen(), rng.gen()),
+                    )
+                } else {
+                    to_world(sample_cosine_hemisphere(rng.gen(), rng.gen()), n)
+                }
+            }
+            Bsdf::Conductor { roughness, .. } => {
+                let alpha = ggx_alpha(roughness);
+                reflect(
+                    -1.0 * wo,
+                    sample_ggx_visible(n, wo, alpha, rng.gen(), rng.gen()),
+                )
+            }
+            Bsdf::Dielectric { eta, roughness, .. } => {
+                let h = sample_ggx_visible(n, wo, ggx_alpha(roughness), rng.gen(), rng.gen());
+                let fresnel = fresnel_dielectric(wo.dot(h), eta);
+                match refract(-1.0 * wo, h, eta) {
+                    Some(refracted) if rng.gen::<f32>() >= fresnel => refracted,
+                    _ => reflect(-1.0 * wo, h),
+                }
+            }
+        };
+
+        let pdf = self.pdf(n, wo, dir);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            dir,
+            weight: self.eval(n, wo, dir) / pdf,
+            pdf,
+        })
+    }
+
+    // solid angle pdf of sample choosing wi
+    pub fn pdf(&self, n: Vec3, wo: Vec3, wi: Vec3) -> f32 {
+        let (cos_o, cos_i) = (n.dot(wo), n.dot(wi));
+        if cos_o <= 0.0 || (cos_i <= 0.0 && !self.is_transmissive()) {
+            return 0.0;
+        }
+        match *self {
+            Bsdf::Diffuse { .. } => cos_i / PI,
+            Bsdf::Pbr {
+                color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+            } => {
+                let p_coat = clearcoat_probability(clearcoat);
+                let p_specular = pbr_specular_probability(color, metallic, specular);
+                let cos_h = n.dot((wo + wi).normalize());
+                let coat_pdf = ggx_visible_pdf(cos_h, cos_o, ggx_alpha(clearcoat_roughness));
+                let specular_pdf = ggx_visible_pdf(cos_h, cos_o, ggx_alpha(roughness));
+                let base_pdf = p_specular * specular_pdf + (1.0 - p_specular) * cos_i / PI;
+                p_coat * coat_pdf + (1.0 - p_coat) * base_pdf
+            }
+            Bsdf::Conductor { roughness, .. } => {
+                let cos_h = n.dot((wo + wi).normalize());
+                ggx_visible_pdf(cos_h, cos_o, ggx_alpha(roughness))
+            }
+            Bsdf::Dielectric { eta, roughness, .. } => {
+                let alpha = ggx_alpha(roughness);
+                if cos_i > 0.0 {
+                    let h = (wo + wi).normalize();
+                    let fresnel = fresnel_dielectric(wo.dot(h), eta);
+                    return fresnel * ggx_visible_pdf(n.dot(h), cos_o, alpha);
+                }
+                let h = match refraction_half_vector(n, wo, wi, eta) {
+                    Some(h) => h,
+                    None => return 0.0,
+                };
+                let (o_h, i_h) = (wo.dot(h), wi.dot(h));
+                let denom = eta * o_h + i_h;
+                let fresnel = fresnel_dielectric(o_h, eta);
+                // density of visible normals, through the change of variables to wi
+                let visible = ggx_g1(cos_o, alpha) * o_h * ggx_d(n.dot(h), alpha) / cos_o;
+                (1.0 - fresnel) * visible * -i_h / (denom * denom)
+            }
+        }
+    }
+}
+
+// D * G / (4 cos_o cos_i) for reflection from wo to wi
+fn microfacet_reflection(n: Vec3, wo: Vec3, wi: Vec3, alpha: f32) -> f32 {
+    let (cos_o, cos_i) = (n.dot(wo), n.dot(wi));
+    let h = (wo + wi).normalize();
+    ggx_d(n.dot(h), alpha) * ggx_g2(cos_o, cos_i, alpha) / (4.0 * cos_o * cos_i)
+}
+
+// microfacet normal refracting wo into wi, on the side of n; None if no
+// microfacet does
+fn refraction_half_vector(n: Vec3, wo: Vec3, wi: Vec3, eta: f32) -> Option<Vec3> {
+    let mut h = (-1.0 * (eta * wo + wi)).normalize();
+    if h.dot(n) < 0.0 {
+        h = -1.0 * h;
+    }
+    if wo.dot(h) <= 0.0 || wi.dot(h) >= 0.0 {
+        return None;
+    }
+    Some(h)
+}
+
+fn clearcoat_probability(clearcoat: f32) -> f32 {
+    0.25 * clearcoat.clamp(0.0, 1.0)
+}
+
+fn pbr_f0(color: Color, metallic: f32, specular: f32) -> Color {
+    (1.0 - metallic) * Color::white() * (0.08 * specular) + metallic * color
+}
+
+// chance of sampling the specular lobe, following the albedo of both lobes
+// but keeping some highlights sampled on bright diffuse surfaces
+fn pbr_specular_probability(color: Color, metallic: f32, specular: f32) -> f32 {
+    let specular_weight = pbr_f0(color, metallic, specular).luminance();
+    let diffuse_weight = (1.0 - metallic) * color.luminance();
+    if diffuse_weight <= 0.0 {
+        return 1.0;
+    }
+    (specular_weight / (specular_weight + diffuse_weight)).max(0.25)
+}
+
+// unpolarized Fresnel reflectance of a conductor, per channel
+pub fn fresnel_conductor(cos: f32, eta: Color, k: Color) -> Color {
+    let channel = |eta: f32, k: f32| {
+        let cos2 = cos.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_b2 + t0)).max(0.0).sqrt();
+        let t1 = a2_b2 + cos2;
+        let t2 = 2.0 * a * cos;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Color::new(
+        channel(eta.r, k.r),
+        channel(eta.g, k.g),
+        channel(eta.b, k.b),
+    )
+}
+
+pub fn fresnel_schlick(f0: Color, cos: f32) -> Color {
+    let t = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color::white() - f0) * t
+}
+
+// keeps very smooth surfaces from becoming numerically delta
+pub fn ggx_alpha(roughness: f32) -> f32 {
+    (roughness * roughness).max(0.002)
+}
+
+// GGX distribution of microfacet normals
+pub fn ggx_d(cos_h: f32, alpha: f32) -> f32 {
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let t = cos_h * cos_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * t * t)
+}
+
+fn ggx_lambda(cos: f32, alpha: f32) -> f32 {
+    let cos2 = cos * cos;
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    0.5 * ((1.0 + alpha * alpha * tan2).sqrt() - 1.0)
+}
+
+pub fn ggx_g1(cos: f32, alpha: f32) -> f32 {
+    1.0 / (1.0 + ggx_lambda(cos, alpha))
+}
+
+// height correlated masking and shadowing
+pub fn ggx_g2(cos_o: f32, cos_i: f32, alpha: f32) -> f32 {
+    1.0 / (1.0 + ggx_lambda(cos_o, alpha) + ggx_lambda(cos_i, alpha))
+}
+
+// microfacet normal from the distribution of normals visible from wo
+// (Heitz 2018), which wastes no samples on backfacing microfacets
+pub fn sample_ggx_visible(n: Vec3, wo: Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    let (t, b) = orthonormal_basis(n);
+    let v = Vec3::new(alpha * wo.dot(t), alpha * wo.dot(b), wo.dot(n)).normalize();
+
+    let len2 = v.x * v.x + v.y * v.y;
+    let t1 = if len2 > 0.0 {
+        Vec3::new(-v.y, v.x, 0.0) / len2.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = v.cross(t1);
+    let (p1, p2) = sample_disk(u1, u2);
+    let s = 0.5 * (1.0 + v.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * p2;
+    let h = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+    let local = Vec3::new(alpha * h.x, alpha * h.y, h.z.max(0.0)).normalize();
+    local.x * t + local.y * b + local.z * n
+}
+
+// pdf of the reflected direction for a microfacet normal from sample_ggx_visible
+pub fn ggx_visible_pdf(cos_h: f32, cos_o: f32, alpha: f32) -> f32 {
+    ggx_g1(cos_o, alpha) * ggx_d(cos_h, alpha) / (4.0 * cos_o)
+}
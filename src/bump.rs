@@ -0,0 +1,75 @@
+use crate::*;
+
+// step in uv for the finite differences of a height map
+const HEIGHT_DELTA: f32 = 0.001;
+
+// Perturbation of the shading normal of a shape, in the tangent frame of the hit.
+// The frame follows the texture coordinates: x along u, y along v, z out of the
+// surface.
+#[derive(Clone, Debug)]
+pub enum Bump {
+    // tangent space normals encoded as colors, (0.5, 0.5, 1) is unperturbed
+    Normal {
+        map: Texture,
+        strength: f32, // scales the tilt of the normals
+    },
+    // heights above the surface, whose slope tilts the normal
+    Height {
+        map: Texture,
+        scale: f32, // height of a texture value of 1, in the units of the shape
+    },
+}
+
+impl Bump {
+    // the shading normal at a hit; it stays on the side of the geometric normal
+    pub fn perturb(&self, info: &Intersection) -> Vec3 {
+        let n = info.normal.normalize();
+        let (t, b) = tangent_frame(n, info.tangent, info.bitangent);
+        let perturbed = match self {
+            Bump::Normal { map, strength } => {
+                let c = map.eval(info);
+                let (x, y, z) = (2.0 * c.r - 1.0, 2.0 * c.g - 1.0, 2.0 * c.b - 1.0);
+                *strength * (x * t + y * b) + z.max(0.0) * n
+            }
+            Bump::Height { map, scale } => {
+                let height = |du: f32, dv: f32| {
+                    let mut shifted = info.clone();
+                    shifted.uv = (info.uv.0 + du, info.uv.1 + dv);
+                    let (local_t, local_b) = info.local_tangents;
+                    shifted.local_pos = info.local_pos + du * local_t + dv * local_b;
+                    *scale * map.scalar(&shifted)
+                };
+                let h = height(0.0, 0.0);
+                let dh_du = (height(HEIGHT_DELTA, 0.0) - h) / HEIGHT_DELTA;
+                let dh_dv = (height(0.0, HEIGHT_DELTA) - h) / HEIGHT_DELTA;
+                // slopes per unit of length along t and b
+                let slope_t = dh_du / info.tangent.length().max(1e-6);
+                let slope_b = dh_dv / info.bitangent.length().max(1e-6);
+                n - slope_t * t - slope_b * b
+            }
+        };
+
+        let perturbed = perturbed.normalize();
+        if perturbed.dot(n) > 0.0 {
+            perturbed
+        } else {
+            n
+        }
+    }
+}
+
+// unit tangent along u and bitangent along v, both perpendicular to n, with any
+// frame around n where the tangent is degenerate such as at the poles of a sphere
+fn tangent_frame(n: Vec3, tangent: Vec3, bitangent: Vec3) -> (Vec3, Vec3) {
+    let t = tangent - n.dot(tangent) * n;
+    if t.length() < 1e-6 {
+        return orthonormal_basis(n);
+    }
+    let t = t.normalize();
+    let b = n.cross(t);
+    if b.dot(bitangent) < 0.0 {
+        (t, -1.0 * b)
+    } else {
+        (t, b)
+    }
+}
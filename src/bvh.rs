@@ -0,0 +1,265 @@
+use crate::*;
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f32 = 1.0;
+
+#[derive(Clone)]
+struct BvhNode {
+    bounding_box: (Vec3, Vec3),
+    start: usize, // first child node if interior, first entry of indices if leaf
+    count: usize, // 0 if interior
+}
+
+#[derive(Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>, // tested against every ray
+}
+
+impl Bvh {
+    pub fn build(bounding_boxes: &[(Vec3, Vec3)]) -> Bvh {
+        let (indices, unbounded) =
+            (0..bounding_boxes.len()).partition(|&i| is_finite_box(bounding_boxes[i]));
+        let mut bvh = Bvh {
+            nodes: vec![],
+            indices,
+            unbounded,
+        };
+        if bvh.indices.is_empty() {
+            return bvh;
+        }
+
+        let centroids: Vec<Vec3> = bounding_boxes
+            .iter()
+            .map(|(min, max)| 0.5 * (*min + *max))
+            .collect();
+        bvh.nodes.push(BvhNode {
+            bounding_box: empty_box(),
+            start: 0,
+            count: 0,
+        });
+        let count = bvh.indices.len();
+        bvh.build_node(0, 0, count, bounding_boxes, &centroids);
+        bvh
+    }
+
+    fn build_node(
+        &mut self,
+        node: usize,
+        start: usize,
+        end: usize,
+        bounding_boxes: &[(Vec3, Vec3)],
+        centroids: &[Vec3],
+    ) {
+        let mut bounding_box = empty_box();
+        let mut centroid_box = empty_box();
+        for &i in &self.indices[start..end] {
+            bounding_box = union(bounding_box, bounding_boxes[i]);
+            centroid_box = union(centroid_box, (centroids[i], centroids[i]));
+        }
+        self.nodes[node] = BvhNode {
+            bounding_box,
+            start,
+            count: end - start,
+        };
+
+        let count = end - start;
+        if count <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        // binned surface area heuristic: (cost, axis, last bin of the left child)
+        let mut best: Option<(f32, usize, usize)> = None;
+        for axis in [0, 1, 2] {
+            let low = centroid_box.0[axis];
+            let extent = centroid_box.1[axis] - low;
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bins = [(empty_box(), 0); BIN_COUNT];
+            for &i in &self.indices[start..end] {
+                let bin = bin_index(centroids[i][axis], low, extent);
+                bins[bin].0 = union(bins[bin].0, bounding_boxes[i]);
+                bins[bin].1 += 1;
+            }
+
+            let mut right_cost = [0.0; BIN_COUNT];
+            let mut right_box = empty_box();
+            let mut right_count = 0;
+            for bin in (1..BIN_COUNT).rev() {
+                right_box = union(right_box, bins[bin].0);
+                right_count += bins[bin].1;
+                right_cost[bin] = sah_cost(right_count, right_box);
+            }
+
+            let mut left_box = empty_box();
+            let mut left_count = 0;
+            for bin in 0..BIN_COUNT - 1 {
+                left_box = union(left_box, bins[bin].0);
+                left_count += bins[bin].1;
+                let cost = sah_cost(left_count, left_box) + right_cost[bin + 1];
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, bin));
+                }
+            }
+        }
+
+        // every centroid coincides, so no split can separate them
+        let Some((cost, axis, split)) = best else {
+            return;
+        };
+        if TRAVERSAL_COST + cost / surface_area(bounding_box) >= count as f32 {
+            return;
+        }
+
+        let low = centroid_box.0[axis];
+        let extent = centroid_box.1[axis] - low;
+        let mut mid = start;
+        for i in start..end {
+            if bin_index(centroids[self.indices[i]][axis], low, extent) <= split {
+                self.indices.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == start || mid == end {
+            return;
+        }
+
+        let left = self.nodes.len();
+        for _ in 0..2 {
+            self.nodes.push(BvhNode {
+                bounding_box: empty_box(),
+                start: 0,
+                count: 0,
+            });
+        }
+        self.nodes[node].start = left;
+        self.nodes[node].count = 0;
+        self.build_node(left, start, mid, bounding_boxes, centroids);
+        self.build_node(left + 1, mid, end, bounding_boxes, centroids);
+    }
+
+    // calls `intersect` for every candidate index and returns the closest hit
+    pub fn traverse<'a, F>(&self, ray: Ray, mut intersect: F) -> Option<Intersection<'a>>
+    where
+        F: FnMut(usize) -> Option<Intersection<'a>>,
+    {
+        let mut closest: Option<Intersection> = None;
+        for &i in &self.unbounded {
+            if let Some(info) = intersect(i) {
+                if closest.as_ref().is_none_or(|cur_info| info.t < cur_info.t) {
+                    closest = Some(info);
+                }
+            }
+        }
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inv_dir = Vec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+        let mut stack = Vec::with_capacity(64);
+        if let Some(t) = intersect_aabb(self.nodes[0].bounding_box, ray.pos, inv_dir) {
+            stack.push((0, t));
+        }
+
+        while let Some((node_index, t)) = stack.pop() {
+            if closest.as_ref().is_some_and(|info| info.t < t) {
+                continue;
+            }
+
+            let node = &self.nodes[node_index];
+            if node.count > 0 {
+                for &i in &self.indices[node.start..node.start + node.count] {
+                    if let Some(info) = intersect(i) {
+                        if closest.as_ref().is_none_or(|cur_info| info.t < cur_info.t) {
+                            closest = Some(info);
+                        }
+                    }
+                }
+            } else {
+                let left = node.start;
+                let right = node.start + 1;
+                let t_left = intersect_aabb(self.nodes[left].bounding_box, ray.pos, inv_dir);
+                let t_right = intersect_aabb(self.nodes[right].bounding_box, ray.pos, inv_dir);
+                // push the farther child first so that the nearer one is visited first
+                match (t_left, t_right) {
+                    (Some(tl), Some(tr)) => {
+                        if tl <= tr {
+                            stack.push((right, tr));
+                            stack.push((left, tl));
+                        } else {
+                            stack.push((left, tl));
+                            stack.push((right, tr));
+                        }
+                    }
+                    (Some(tl), None) => stack.push((left, tl)),
+                    (None, Some(tr)) => stack.push((right, tr)),
+                    (None, None) => {}
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+// returns the entry distance of the ray into the box, if it hits
+pub fn intersect_aabb(bounding_box: (Vec3, Vec3), pos: Vec3, inv_dir: Vec3) -> Option<f32> {
+    let t1 = (bounding_box.0.x - pos.x) * inv_dir.x;
+    let t2 = (bounding_box.1.x - pos.x) * inv_dir.x;
+    let t3 = (bounding_box.0.y - pos.y) * inv_dir.y;
+    let t4 = (bounding_box.1.y - pos.y) * inv_dir.y;
+    let t5 = (bounding_box.0.z - pos.z) * inv_dir.z;
+    let t6 = (bounding_box.1.z - pos.z) * inv_dir.z;
+    let tmin = t1.min(t2).max(t3.min(t4)).max(t5.min(t6));
+    let tmax = t1.max(t2).min(t3.max(t4)).min(t5.max(t6));
+    if tmax < 0.0 || tmin > tmax {
+        None
+    } else {
+        Some(tmin.max(0.0))
+    }
+}
+
+pub fn empty_box() -> (Vec3, Vec3) {
+    (
+        Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+    )
+}
+
+pub fn infinite_box() -> (Vec3, Vec3) {
+    (
+        Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+    )
+}
+
+pub fn is_finite_box(bounding_box: (Vec3, Vec3)) -> bool {
+    [bounding_box.0, bounding_box.1]
+        .iter()
+        .all(|v| v.x.is_finite() && v.y.is_finite() && v.z.is_finite())
+}
+
+pub fn union(a: (Vec3, Vec3), b: (Vec3, Vec3)) -> (Vec3, Vec3) {
+    (a.0.min(b.0), a.1.max(b.1))
+}
+
+fn surface_area(bounding_box: (Vec3, Vec3)) -> f32 {
+    let d = bounding_box.1 - bounding_box.0;
+    2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+fn sah_cost(count: usize, bounding_box: (Vec3, Vec3)) -> f32 {
+    if count == 0 {
+        0.0
+    } else {
+        count as f32 * surface_area(bounding_box)
+    }
+}
+
+fn bin_index(centroid: f32, low: f32, extent: f32) -> usize {
+    (((centroid - low) / extent * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)
+}
@@ -0,0 +1,148 @@
+use rand::Rng;
+use std::f32::consts::PI;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic { height: f32 }, // height of the view in world units
+    Equirectangular,              // full 360 * 180 degree panorama
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub projection: Projection,
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    pub fov: f32,                    // vertical field of view in degrees
+    pub aspect: Option<f32>,         // width / height, follows the image if None
+    pub aperture: f32,               // lens radius, 0 for a pinhole, perspective only
+    pub focus_distance: Option<f32>, // distance to the look_at target if None
+}
+
+// the beam of rays through one pixel, for choosing how much texture detail it
+// can resolve; width grows by `spread` per unit of distance
+#[derive(Clone, Copy, Debug)]
+pub struct RayCone {
+    pub width: f32,
+    pub spread: f32,
+}
+
+impl RayCone {
+    pub fn width_at(&self, distance: f32) -> f32 {
+        self.width + self.spread * distance.max(0.0)
+    }
+
+    // the cone continuing from a hit at `distance`
+    pub fn advance(&self, distance: f32) -> RayCone {
+        RayCone {
+            width: self.width_at(distance),
+            spread: self.spread,
+        }
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        // image plane of height 1 at distance 1, looking down -z
+        Camera {
+            projection: Projection::Perspective,
+            position: Vec3::new(0.0, 0.0, 1.0),
+            look_at: Vec3::new(0.0, 0.0, 0.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            fov: 2.0 * 0.5f32.atan().to_degrees(),
+            aspect: None,
+            aperture: 0.0,
+            focus_distance: None,
+        }
+    }
+}
+
+impl Camera {
+    // (right, up, forward), orthonormal
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.look_at - self.position).normalize();
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward);
+        (right, up, forward)
+    }
+
+    pub fn pixel_cone(&self, height: u32) -> RayCone {
+        let pixels = height as f32;
+        match self.projection {
+            Projection::Perspective => RayCone {
+                width: 0.0,
+                spread: 2.0 * (0.5 * self.fov).to_radians().tan() / pixels,
+            },
+            Projection::Orthographic { height } => RayCone {
+                width: height / pixels,
+                spread: 0.0,
+            },
+            Projection::Equirectangular => RayCone {
+                width: 0.0,
+                spread: PI / pixels,
+            },
+        }
+    }
+
+    // x and y are in pixels, with (0, 0) at the top left corner of the image
+    pub fn generate_ray<R: Rng>(
+        &self,
+        x: f32,
+        y: f32,
+        width: u32,
+        height: u32,
+        rng: &mut R,
+    ) -> Ray {
+        let (right, up, forward) = self.basis();
+        let aspect = self.aspect.unwrap_or(width as f32 / height as f32);
+        let u = 2.0 * x / width as f32 - 1.0;
+        let v = 1.0 - 2.0 * y / height as f32;
+
+        match self.projection {
+            Projection::Perspective => {}
+            Projection::Orthographic { height } => {
+                let half_h = 0.5 * height;
+                let half_w = half_h * aspect;
+                return Ray {
+                    pos: self.position + u * half_w * right + v * half_h * up,
+                    dir: forward,
+                };
+            }
+            Projection::Equirectangular => {
+                // the center of the image looks at the target
+                let longitude = u * PI;
+                let latitude = v * 0.5 * PI;
+                let horizontal = longitude.sin() * right + longitude.cos() * forward;
+                return Ray {
+                    pos: self.position,
+                    dir: (latitude.cos() * horizontal + latitude.sin() * up).normalize(),
+                };
+            }
+        }
+
+        let half_h = (0.5 * self.fov).to_radians().tan();
+        let half_w = half_h * aspect;
+        let dir = forward + u * half_w * right + v * half_h * up;
+        if self.aperture <= 0.0 {
+            return Ray {
+                pos: self.position,
+                dir: dir.normalize(),
+            };
+        }
+
+        // dir has unit length along forward, so this lands on the plane of focus
+        let focus_distance = self
+            .focus_distance
+            .unwrap_or((self.look_at - self.position).length());
+        let focus_point = self.position + focus_distance * dir;
+        let (lens_x, lens_y) = sample_disk(rng.gen(), rng.gen());
+        let lens_pos = self.position + self.aperture * lens_x * right + self.aperture * lens_y * up;
+        Ray {
+            pos: lens_pos,
+            dir: (focus_point - lens_pos).normalize(),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::*;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub threads: u32,
+    pub max_depth: u32,
+    pub integrator: Integrator,
+    pub env: Env,
+    pub scene_file: Option<String>,
+    pub save_scene: Option<String>,
+    pub output: String,
+    pub tone_map: ToneMap,
+    pub exposure: f32,  // in stops
+    pub bit_depth: u32, // of tone mapped outputs
+    pub aovs: Vec<Aov>,
+    pub aov_layers: bool, // write the aovs into the output instead of their own files
+    pub seed: u64,
+    pub bench: bool,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            width: 1280,
+            height: 720,
+            samples: 16,
+            threads: thread::available_parallelism().map_or(16, |n| n.get() as u32),
+            max_depth: 5,
+            integrator: Integrator::Whitted,
+            env: Env::Default,
+            scene_file: None,
+            save_scene: None,
+            output: String::from("asdf.png"),
+            tone_map: ToneMap::Clamp,
+            exposure: 0.0,
+            bit_depth: 8,
+            aovs: vec![],
+            aov_layers: false,
+            seed: rand::random(),
+            bench: false,
+            help: false,
+        }
+    }
+}
+
+pub fn usage() -> String {
+    let default = Options::default();
+    format!(
+        "usage: ray_tracing_study [options]
+
+options:
+  --width <pixels>     image width (default {})
+  --height <pixels>    image height (default {})
+  --spp <count>        samples per pixel (default {})
+  --threads <count>    render threads (default {})
+  --max-depth <count>  maximum number of bounces per camera ray (default {})
+  --integrator <name>  shading: {} (default whitted)
+  --scene <name>       built-in scene: {} (default default)
+  --scene-file <path>  scene description file, overrides --scene
+  --save-scene <path>  write the loaded scene as a scene file
+  --output <path>      output image path (default {}); .exr and .hdr files
+                       keep the linear radiance, other formats are tone mapped
+  --tonemap <name>     tone mapping: {} (default clamp)
+  --exposure <stops>   brightness scale before tone mapping, 2^stops (default 0)
+  --bit-depth <8|16>   bits per channel of tone mapped outputs, 16 for png and tiff
+                       (default 8)
+  --aov <names>        comma separated output passes: {};
+                       written to <output without extension>.<name>.exr
+  --aov-layers         write the passes as layers of the output, which has to be .exr
+  --seed <number>      random seed (default random)
+  --bench              run the ray-cast benchmark and exit
+  --help               print this message",
+        default.width,
+        default.height,
+        default.samples,
+        default.threads,
+        default.max_depth,
+        Integrator::NAMES.join(", "),
+        Env::NAMES.join(", "),
+        default.output,
+        ToneMap::NAMES.join(", "),
+        Aov::NAMES.join(", "),
+    )
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => options.width = parse_positive(&arg, args.next())?,
+            "--height" => options.height = parse_positive(&arg, args.next())?,
+            "--spp" => options.samples = parse_positive(&arg, args.next())?,
+            "--threads" => options.threads = parse_positive(&arg, args.next())?,
+            "--max-depth" => options.max_depth = parse_number(&arg, args.next())?,
+            "--integrator" => {
+                let name = expect_value(&arg, args.next())?;
+                options.integrator = Integrator::from_name(&name).ok_or(format!(
+                    "unknown integrator '{}', expected one of: {}",
+                    name,
+                    Integrator::NAMES.join(", ")
+                ))?;
+            }
+            "--scene" => {
+                let name = expect_value(&arg, args.next())?;
+                options.env = Env::from_name(&name).ok_or(format!(
+                    "unknown scene '{}', expected one of: {}",
+                    name,
+                    Env::NAMES.join(", ")
+                ))?;
+            }
+            "--scene-file" => options.scene_file = Some(expect_value(&arg, args.next())?),
+            "--save-scene" => options.save_scene = Some(expect_value(&arg, args.next())?),
+            "--output" => options.output = expect_value(&arg, args.next())?,
+            "--tonemap" => {
+                let name = expect_value(&arg, args.next())?;
+                options.tone_map = ToneMap::from_name(&name).ok_or(format!(
+                    "unknown tone mapping '{}', expected one of: {}",
+                    name,
+                    ToneMap::NAMES.join(", ")
+                ))?;
+            }
+            "--exposure" => {
+                let value = expect_value(&arg, args.next())?;
+                options.exposure =
+                    value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|e| e.is_finite())
+                        .ok_or(format!(
+                            "invalid value '{}' for {}: expected a number",
+                            value, arg
+                        ))?;
+            }
+            "--seed" => {
+                let value = expect_value(&arg, args.next())?;
+                options.seed = value.parse().map_err(|_| {
+                    format!(
+                        "invalid value '{}' for {}: expected a non-negative integer",
+                        value, arg
+                    )
+                })?;
+            }
+            "--bit-depth" => {
+                let value = expect_value(&arg, args.next())?;
+                options.bit_depth = match value.as_str() {
+                    "8" => 8,
+                    "16" => 16,
+                    _ => {
+                        return Err(format!(
+                            "invalid value '{}' for {}: expected 8 or 16",
+                            value, arg
+                        ))
+                    }
+                };
+            }
+            "--aov" => {
+                let value = expect_value(&arg, args.next())?;
+                for name in value.split(',') {
+                    let aov = Aov::from_name(name).ok_or(format!(
+                        "unknown output pass '{}', expected one of: {}",
+                        name,
+                        Aov::NAMES.join(", ")
+                    ))?;
+                    if !options.aovs.contains(&aov) {
+                        options.aovs.push(aov);
+                    }
+                }
+            }
+            "--aov-layers" => options.aov_layers = true,
+            "--bench" => options.bench = true,
+            "--help" | "-h" => options.help = true,
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+
+    // fail before rendering rather than when saving
+    let format = match image::ImageFormat::from_path(&options.output) {
+        Ok(format) => format,
+        Err(_) => return Err(format!("unknown image format of '{}'", options.output)),
+    };
+    if options.bit_depth == 16 && !matches!(format, ImageFormat::Png | ImageFormat::Tiff) {
+        return Err(String::from("--bit-depth 16 needs a .png or .tiff output"));
+    }
+    if options.aov_layers && format != ImageFormat::OpenExr {
+        return Err(String::from("--aov-layers needs an .exr output"));
+    }
+
+    if options.threads > options.width {
+        options.threads = options.width;
+    }
+
+    Ok(options)
+}
+
+fn expect_value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or(format!("missing value for {}", option))
+}
+
+fn parse_number(option: &str, value: Option<String>) -> Result<u32, String> {
+    let value = expect_value(option, value)?;
+    value.parse::<u32>().map_err(|_| {
+        format!(
+            "invalid value '{}' for {}: expected a non-negative integer",
+            value, option
+        )
+    })
+}
+
+fn parse_positive(option: &str, value: Option<String>) -> Result<u32, String> {
+    let value = expect_value(option, value)?;
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid value '{}' for {}: expected a positive integer",
+            value, option
+        )),
+    }
+}
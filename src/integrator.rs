@@ -0,0 +1,420 @@
+use rand::Rng;
+use std::f32::consts::PI;
+
+use crate::*;
+
+const EPSILON: f32 = 0.0001;
+const ROULETTE_DEPTH: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    Whitted,
+    Path,
+}
+
+impl Integrator {
+    pub const NAMES: [&'static str; 2] = ["whitted", "path"];
+
+    pub fn from_name(name: &str) -> Option<Integrator> {
+        match name {
+            "whitted" => Some(Integrator::Whitted),
+            "path" => Some(Integrator::Path),
+            _ => None,
+        }
+    }
+
+    pub fn trace<R: Rng>(
+        &self,
+        scene: &Scene,
+        ray: Ray,
+        cone: RayCone,
+        max_depth: u32,
+        rng: &mut R,
+    ) -> Color {
+        match self {
+            Integrator::Whitted => trace_whitted(scene, ray, cone, 0, max_depth, rng),
+            Integrator::Path => trace_path(scene, ray, cone, max_depth, rng),
+        }
+    }
+}
+
+// Whitted-style ray tracing: direct and ambient light on opaque surfaces, and
+// recursive rays for mirrors and glass up to `max_depth` bounces
+pub fn trace_whitted<R: Rng>(
+    scene: &Scene,
+    ray: Ray,
+    cone: RayCone,
+    depth: u32,
+    max_depth: u32,
+    rng: &mut R,
+) -> Color {
+    let mut info = match scene.root.intersect(ray) {
+        Some(info) => info,
+        None => return scene.background.radiance(ray.dir.normalize()),
+    };
+
+    let dir = ray.dir.normalize();
+    prepare_shading(&mut info, dir, cone);
+    let normal = info.normal.normalize();
+    let cone = cone.advance(info.t);
+    let color = info.material.color(&info);
+    match *info.material {
+        Material::Simple { .. } | Material::Pbr { .. } | Material::Conductor { .. } => {
+            let bsdf = info.material.bsdf(&info);
+            let (n, wo) = (face_forward(dir, normal), -1.0 * dir);
+            direct_light(scene, info.pos, n, wo, &bsdf, false, rng)
+                + ambient_light(scene, info.pos, n, wo, &bsdf, rng)
+        }
+        Material::Mirror { .. } => {
+            if depth >= max_depth {
+                return Color::black();
+            }
+            let reflected = reflect(dir, normal);
+            color
+                * trace_whitted(
+                    scene,
+                    offset_ray(info.pos, reflected),
+                    cone,
+                    depth + 1,
+                    max_depth,
+                    rng,
+                )
+        }
+        Material::Emissive { .. } => info.material.emission(&info),
+        Material::Glass { ior, .. } => {
+            if depth >= max_depth {
+                return Color::black();
+            }
+
+            let (n, eta, cos_i) = facing_interface(dir, normal, info.front_face, ior);
+            let reflectance = fresnel_dielectric(cos_i, eta);
+            let reflected = reflect(dir, n);
+            let mut result = reflectance
+                * trace_whitted(
+                    scene,
+                    offset_ray(info.pos, reflected),
+                    cone,
+                    depth + 1,
+                    max_depth,
+                    rng,
+                );
+            if let Some(refracted) = refract(dir, n, eta) {
+                result += (1.0 - reflectance)
+                    * color
+                    * trace_whitted(
+                        scene,
+                        offset_ray(info.pos, refracted),
+                        cone,
+                        depth + 1,
+                        max_depth,
+                        rng,
+                    );
+            }
+            result
+        }
+        Material::RoughGlass { .. } => {
+            if depth >= max_depth {
+                return Color::black();
+            }
+
+            // one direction through or off the surface, like a path tracer would
+            let bsdf = info.material.bsdf(&info);
+            let (n, wo) = (face_forward(dir, normal), -1.0 * dir);
+            match bsdf.sample(n, wo, rng) {
+                Some(sample) => {
+                    sample.weight
+                        * trace_whitted(
+                            scene,
+                            offset_ray(info.pos, sample.dir),
+                            cone,
+                            depth + 1,
+                            max_depth,
+                            rng,
+                        )
+                }
+                None => Color::black(),
+            }
+        }
+    }
+}
+
+// Unbiased path tracing: bsdf sampled bounces off opaque surfaces with
+// next event estimation toward every light, terminated by Russian roulette
+pub fn trace_path<R: Rng>(
+    scene: &Scene,
+    ray: Ray,
+    cone: RayCone,
+    max_depth: u32,
+    rng: &mut R,
+) -> Color {
+    let mut radiance = Color::black();
+    let mut throughput = Color::white();
+    let mut ray = ray;
+    let mut cone = cone;
+    // pdf of the last opaque bounce, for weighting the background and emitters
+    // it finds against next event estimation; none after specular bounces
+    let mut bounce_pdf = None;
+
+    for depth in 0..=max_depth {
+        let mut info = match scene.root.intersect(ray) {
+            Some(info) => info,
+            None => {
+                let dir = ray.dir.normalize();
+                let background_pdf = scene.background.pdf(dir);
+                let weight = match bounce_pdf {
+                    Some(pdf) if background_pdf > 0.0 => power_heuristic(pdf, background_pdf),
+                    _ => 1.0,
+                };
+                radiance += throughput * scene.background.radiance(dir) * weight;
+                break;
+            }
+        };
+
+        let dir = ray.dir.normalize();
+        prepare_shading(&mut info, dir, cone);
+        let normal = info.normal.normalize();
+        let color = info.material.color(&info);
+        let next_dir = match *info.material {
+            Material::Emissive { .. } => {
+                let light_pdf = scene
+                    .emitters
+                    .iter()
+                    .find(|(id, _)| *id == info.object_id)
+                    .map_or(0.0, |(_, light)| {
+                        light.pdf(ray.pos, dir, info.t * ray.dir.length(), normal)
+                    });
+                let weight = match bounce_pdf {
+                    Some(pdf) if light_pdf > 0.0 => power_heuristic(pdf, light_pdf),
+                    _ => 1.0,
+                };
+                radiance += throughput * info.material.emission(&info) * weight;
+                break;
+            }
+            Material::Simple { .. } | Material::Pbr { .. } | Material::Conductor { .. } => {
+                let bsdf = info.material.bsdf(&info);
+                let (n, wo) = (face_forward(dir, normal), -1.0 * dir);
+                radiance += throughput
+                    * (direct_light(scene, info.pos, n, wo, &bsdf, true, rng)
+                        + environment_light(scene, info.pos, n, wo, &bsdf, rng));
+
+                let sample = match bsdf.sample(n, wo, rng) {
+                    Some(sample) => sample,
+                    None => break,
+                };
+                throughput = throughput * sample.weight;
+                bounce_pdf = Some(sample.pdf);
+                sample.dir
+            }
+            Material::Mirror { .. } => {
+                bounce_pdf = None;
+                throughput = throughput * color;
+                reflect(dir, normal)
+            }
+            Material::Glass { ior, .. } => {
+                bounce_pdf = None;
+                let (n, eta, cos_i) = facing_interface(dir, normal, info.front_face, ior);
+                let reflectance = fresnel_dielectric(cos_i, eta);
+                match refract(dir, n, eta) {
+                    Some(refracted) if rng.gen::<f32>() >= reflectance => {
+                        throughput = throughput * color;
+                        refracted
+                    }
+                    _ => reflect(dir, n),
+                }
+            }
+            Material::RoughGlass { .. } => {
+                // lights are not sampled through glass, so its bounces find emitters
+                bounce_pdf = None;
+                let bsdf = info.material.bsdf(&info);
+                let (n, wo) = (face_forward(dir, normal), -1.0 * dir);
+                let sample = match bsdf.sample(n, wo, rng) {
+                    Some(sample) => sample,
+                    None => break,
+                };
+                throughput = throughput * sample.weight;
+                sample.dir
+            }
+        };
+
+        if depth >= ROULETTE_DEPTH {
+            let survival = throughput.max_component().min(0.95);
+            if rng.gen::<f32>() >= survival {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+        ray = offset_ray(info.pos, next_dir);
+        cone = cone.advance(info.t);
+    }
+
+    radiance
+}
+
+// light reflected toward wo from one sample of every light; with `mis` the
+// samples of emissive shapes are weighted against the bsdf finding them
+fn direct_light<R: Rng>(
+    scene: &Scene,
+    pos: Vec3,
+    normal: Vec3,
+    wo: Vec3,
+    bsdf: &Bsdf,
+    mis: bool,
+    rng: &mut R,
+) -> Color {
+    let mut result = Color::black();
+    // with the object id of the emissive shape, 0 for lights that are not shapes
+    let lights = scene.lights.iter().map(|light| (0, light));
+    let emitters = scene.emitters.iter().map(|(id, light)| (*id, light));
+    for (emitter_id, light) in lights.chain(emitters) {
+        let sample = match light.sample(pos, rng) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        // sample.light is what a white diffuse surface would reflect, 1 / pi of the irradiance
+        let reflectance = PI * bsdf.eval(normal, wo, sample.dir);
+        if reflectance == Color::black() {
+            continue;
+        }
+
+        // a shadow ray toward an emissive shape has to reach it at the sampled point
+        let shadow_ray = offset_ray(pos, sample.dir);
+        let slack = EPSILON * sample.distance + 2.0 * EPSILON;
+        let hit = scene.root.intersect(shadow_ray);
+        let visible = if emitter_id == 0 {
+            hit.is_none_or(|info| info.t >= sample.distance - slack)
+        } else {
+            hit.is_some_and(|info| {
+                info.object_id == emitter_id && info.t >= sample.distance - sample.tolerance - slack
+            })
+        };
+        if visible {
+            let weight = if mis && emitter_id != 0 {
+                power_heuristic(sample.pdf, bsdf.pdf(normal, wo, sample.dir))
+            } else {
+                1.0
+            };
+            result += reflectance * sample.light * weight;
+        }
+    }
+    result
+}
+
+// background light reflected toward wo, from one direction sampled from the
+// bsdf combined with one from an importance sampled background; the path
+// tracer gathers it through its own bounces instead
+fn ambient_light<R: Rng>(
+    scene: &Scene,
+    pos: Vec3,
+    normal: Vec3,
+    wo: Vec3,
+    bsdf: &Bsdf,
+    rng: &mut R,
+) -> Color {
+    if scene.background.is_black() {
+        return Color::black();
+    }
+    let mut result = environment_light(scene, pos, normal, wo, bsdf, rng);
+    if let Some(sample) = bsdf.sample(normal, wo, rng) {
+        if scene.root.intersect(offset_ray(pos, sample.dir)).is_none() {
+            let background_pdf = scene.background.pdf(sample.dir);
+            let weight = if background_pdf > 0.0 {
+                power_heuristic(sample.pdf, background_pdf)
+            } else {
+                1.0
+            };
+            result += scene.background.radiance(sample.dir) * sample.weight * weight;
+        }
+    }
+    result
+}
+
+// next event estimation toward an importance sampled background, weighted
+// against finding the same direction by sampling the bsdf
+fn environment_light<R: Rng>(
+    scene: &Scene,
+    pos: Vec3,
+    normal: Vec3,
+    wo: Vec3,
+    bsdf: &Bsdf,
+    rng: &mut R,
+) -> Color {
+    let (dir, radiance, pdf) = match scene.background.sample(rng) {
+        Some(sample) => sample,
+        None => return Color::black(),
+    };
+    let f = bsdf.eval(normal, wo, dir);
+    if f == Color::black() || scene.root.intersect(offset_ray(pos, dir)).is_some() {
+        return Color::black();
+    }
+    radiance * f * (power_heuristic(pdf, bsdf.pdf(normal, wo, dir)) / pdf)
+}
+
+// the footprint of a hit seen along `dir`, then the bump of its shape, which
+// samples its maps at that footprint
+pub fn prepare_shading(info: &mut Intersection, dir: Vec3, cone: RayCone) {
+    info.footprint = footprint(cone, info.t, dir, info.normal.normalize());
+    if let Some(bump) = info.bump {
+        info.normal = bump.perturb(info);
+    }
+}
+
+// width of the surface covered by the cone, stretched at grazing angles
+fn footprint(cone: RayCone, t: f32, dir: Vec3, normal: Vec3) -> f32 {
+    cone.width_at(t) / dir.dot(normal).abs().max(0.2)
+}
+
+// multiple importance sampling weight of a strategy with pdf `a` against one with pdf `b`
+fn power_heuristic(a: f32, b: f32) -> f32 {
+    // a * a / (a * a + b * b) without overflowing for the huge pdfs of grazing samples
+    1.0 / (1.0 + (b / a).powi(2))
+}
+
+fn face_forward(dir: Vec3, normal: Vec3) -> Vec3 {
+    if dir.dot(normal) > 0.0 {
+        -1.0 * normal
+    } else {
+        normal
+    }
+}
+
+// flips the normal toward the incoming ray: (normal, eta, cos_i), with eta = n_i / n_t
+fn facing_interface(dir: Vec3, normal: Vec3, front_face: bool, ior: f32) -> (Vec3, f32, f32) {
+    let n = face_forward(dir, normal);
+    let eta = if front_face { 1.0 / ior } else { ior };
+    (n, eta, -dir.dot(n))
+}
+
+fn offset_ray(pos: Vec3, dir: Vec3) -> Ray {
+    Ray {
+        pos: pos + EPSILON * dir,
+        dir,
+    }
+}
+
+pub fn reflect(dir: Vec3, normal: Vec3) -> Vec3 {
+    dir - 2.0 * dir.dot(normal) * normal
+}
+
+// `normal` faces the incoming ray, eta = n_incident / n_transmitted;
+// None on total internal reflection
+pub fn refract(dir: Vec3, normal: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_i = -dir.dot(normal);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((eta * dir + (eta * cos_i - cos_t) * normal).normalize())
+}
+
+// unpolarized Fresnel reflectance of a dielectric interface
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let rp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
@@ -0,0 +1,256 @@
+use rand::Rng;
+use std::f32::consts::PI;
+
+use crate::*;
+
+// Intensities are scaled so that a white diffuse surface facing a directional
+// light of intensity 1 reflects 1, and area lights are given as radiance.
+#[derive(Clone, Debug)]
+pub enum Light {
+    Directional {
+        direction: Vec3, // toward the light
+        intensity: Color,
+    },
+    Point {
+        position: Vec3,
+        intensity: Color, // inverse square falloff
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3, // where the spot points
+        intensity: Color,
+        inner_angle: f32, // degrees from the axis, full intensity inside
+        outer_angle: f32, // degrees from the axis, no light outside
+    },
+    Rect {
+        corner: Vec3,
+        edge1: Vec3,
+        edge2: Vec3,
+        radiance: Color, // emitted toward edge1 x edge2
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+        radiance: Color,
+    },
+    // world space triangles of a shape with an emissive material, emitting toward
+    // the side their winding faces
+    Mesh {
+        triangles: Vec<[Vec3; 3]>,
+        cdf: Vec<f32>, // running sum of triangle areas
+        radiance: Color,
+        // how much closer than a sampled point the shape itself may be hit,
+        // infinite for triangles inside a convex curved surface
+        tolerance: f32,
+    },
+}
+
+impl Light {
+    pub fn mesh(triangles: Vec<[Vec3; 3]>, radiance: Color, tolerance: f32) -> Light {
+        let mut area = 0.0;
+        let cdf = triangles
+            .iter()
+            .map(|[a, b, c]| {
+                area += 0.5 * (*b - *a).cross(*c - *a).length();
+                area
+            })
+            .collect();
+        Light::Mesh {
+            triangles,
+            cdf,
+            radiance,
+            tolerance,
+        }
+    }
+}
+
+pub struct LightSample {
+    pub dir: Vec3,      // normalized, toward the light
+    pub distance: f32,  // infinite for directional lights
+    pub light: Color,   // reflected by a white diffuse surface facing the sample
+    pub tolerance: f32, // see Light::Mesh, 0 for the other lights
+    pub pdf: f32,       // solid angle density of dir, infinite for point and directional lights
+}
+
+impl Light {
+    pub fn sample<R: Rng>(&self, pos: Vec3, rng: &mut R) -> Option<LightSample> {
+        match self {
+            Light::Directional {
+                direction,
+                intensity,
+            } => Some(LightSample {
+                dir: direction.normalize(),
+                distance: f32::INFINITY,
+                light: *intensity,
+                tolerance: 0.0,
+                pdf: f32::INFINITY,
+            }),
+            Light::Point {
+                position,
+                intensity,
+            } => {
+                let to_light = *position - pos;
+                let distance = to_light.length();
+                Some(LightSample {
+                    dir: to_light / distance,
+                    distance,
+                    light: *intensity / (distance * distance),
+                    tolerance: 0.0,
+                    pdf: f32::INFINITY,
+                })
+            }
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                let to_light = *position - pos;
+                let distance = to_light.length();
+                let dir = to_light / distance;
+                let cos_theta = -dir.dot(direction.normalize());
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+                let cone = smoothstep(cos_outer, cos_inner, cos_theta);
+                if cone <= 0.0 {
+                    return None;
+                }
+                Some(LightSample {
+                    dir,
+                    distance,
+                    light: cone * *intensity / (distance * distance),
+                    tolerance: 0.0,
+                    pdf: f32::INFINITY,
+                })
+            }
+            Light::Rect {
+                corner,
+                edge1,
+                edge2,
+                radiance,
+            } => {
+                let point = *corner + rng.gen::<f32>() * *edge1 + rng.gen::<f32>() * *edge2;
+                let normal = edge1.cross(*edge2);
+                let area = normal.length();
+                let to_light = point - pos;
+                let distance = to_light.length();
+                let dir = to_light / distance;
+                let cos_light = -dir.dot(normal / area);
+                if cos_light <= 0.0 {
+                    return None;
+                }
+                Some(LightSample {
+                    dir,
+                    distance,
+                    light: *radiance * (cos_light * area / (PI * distance * distance)),
+                    tolerance: 0.0,
+                    pdf: distance * distance / (cos_light * area),
+                })
+            }
+            Light::Sphere {
+                center,
+                radius,
+                radiance,
+            } => {
+                // uniform over the cone of directions subtended by the sphere
+                let to_center = *center - pos;
+                let center_distance = to_center.length();
+                if center_distance <= *radius {
+                    return None;
+                }
+                let axis = to_center / center_distance;
+                let sin_max = radius / center_distance;
+                let cos_max = (1.0 - sin_max * sin_max).max(0.0).sqrt();
+                let cos_theta = 1.0 - rng.gen::<f32>() * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.gen::<f32>();
+                let dir = to_world(
+                    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta),
+                    axis,
+                );
+
+                // nearest intersection with the sphere, or the tangent point at the rim
+                let b = dir.dot(to_center);
+                let c = center_distance * center_distance - radius * radius;
+                let distance = b - (b * b - c).max(0.0).sqrt();
+                Some(LightSample {
+                    dir,
+                    distance,
+                    light: *radiance * (2.0 * (1.0 - cos_max)),
+                    tolerance: 0.0,
+                    pdf: 1.0 / (2.0 * PI * (1.0 - cos_max)),
+                })
+            }
+            Light::Mesh {
+                triangles,
+                cdf,
+                radiance,
+                tolerance,
+            } => {
+                let area = *cdf.last()?;
+                let target = rng.gen::<f32>() * area;
+                let i = cdf
+                    .partition_point(|&a| a < target)
+                    .min(triangles.len() - 1);
+                let [a, b, c] = triangles[i];
+
+                let su = rng.gen::<f32>().sqrt();
+                let (w0, w1) = (1.0 - su, rng.gen::<f32>() * su);
+                let point = w0 * a + w1 * b + (1.0 - w0 - w1) * c;
+                let normal = (b - a).cross(c - a).normalize();
+                let to_light = point - pos;
+                let distance = to_light.length();
+                let dir = to_light / distance;
+                let cos_light = -dir.dot(normal);
+                if cos_light <= 0.0 {
+                    return None;
+                }
+                Some(LightSample {
+                    dir,
+                    distance,
+                    light: *radiance * (cos_light * area / (PI * distance * distance)),
+                    tolerance: *tolerance,
+                    pdf: distance * distance / (cos_light * area),
+                })
+            }
+        }
+    }
+
+    // density with which `sample` picks the direction `dir` from `pos`, toward a
+    // point of the light at `distance` whose normal is `normal`; 0 for lights
+    // that rays cannot hit
+    pub fn pdf(&self, pos: Vec3, dir: Vec3, distance: f32, normal: Vec3) -> f32 {
+        match self {
+            Light::Sphere { center, radius, .. } => {
+                let to_center = *center - pos;
+                let center_distance = to_center.length();
+                if center_distance <= *radius {
+                    return 0.0;
+                }
+                let sin_max = radius / center_distance;
+                let cos_max = (1.0 - sin_max * sin_max).max(0.0).sqrt();
+                if dir.dot(to_center) < cos_max * center_distance {
+                    return 0.0;
+                }
+                1.0 / (2.0 * PI * (1.0 - cos_max))
+            }
+            Light::Mesh { cdf, .. } => {
+                let (area, cos_light) = (cdf.last().copied().unwrap_or(0.0), dir.dot(normal).abs());
+                if area == 0.0 || cos_light == 0.0 {
+                    return 0.0;
+                }
+                distance * distance / (cos_light * area)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge0 >= edge1 {
+        return if x >= edge1 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
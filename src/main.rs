@@ -1,26 +1,58 @@
+mod aov;
+mod background;
+mod bench;
+mod bsdf;
+mod bump;
+mod bvh;
+mod camera;
+mod cli;
+mod integrator;
+mod light;
 mod make_env;
+mod noise;
 mod obj_reader;
+mod output;
+mod sampling;
+mod scene;
+mod scene_file;
 mod shape;
+mod texture;
+mod tonemap;
 mod transform;
 mod util;
 
-use image::{ImageBuffer, Rgb, RgbImage};
+use image::{ImageBuffer, ImageFormat, Rgb, Rgb32FImage, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::process;
 use std::sync::Arc;
 use std::thread;
 use std::time::Instant;
 
+pub use aov::*;
+pub use background::*;
+pub use bench::*;
+pub use bsdf::*;
+pub use bump::*;
+pub use bvh::*;
+pub use camera::*;
+pub use cli::*;
+pub use integrator::*;
+pub use light::*;
 pub use make_env::*;
+pub use noise::*;
 pub use obj_reader::*;
+pub use output::*;
+pub use sampling::*;
+pub use scene::*;
+pub use scene_file::*;
 pub use shape::*;
+pub use texture::*;
+pub use tonemap::*;
 pub use transform::*;
 pub use util::*;
 
-const W: u32 = 1280;
-const H: u32 = 720;
-const THREAD_COUNT: u32 = 16;
-const CURRENT_ENV: Env = Env::Default;
-const SAMPLE_NUMBER: u32 = 16;
-
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
     pos: Vec3,
@@ -28,122 +60,171 @@ pub struct Ray {
 }
 
 #[derive(Clone, Debug)]
-pub struct Intersection {
+pub struct Intersection<'a> {
     t: f32,
     pos: Vec3,
     normal: Vec3,
-    local_frame: Mat4,
-    material: Material,
+    local_pos: Vec3, // position in the local space of the shape that was hit
+    uv: (f32, f32),  // texture coordinates
+    tangent: Vec3,   // change of pos along u, not normalized
+    bitangent: Vec3, // change of pos along v, not normalized
+    uv_density: f32, // change of uv per unit of distance along the surface
+    footprint: f32,  // width of the surface seen by one pixel, 0 if unknown
+    bump: Option<&'a Bump>,
+    local_tangents: (Vec3, Vec3), // tangent and bitangent before any transform, for a bump
+    front_face: bool,             // the ray hit the outside of the surface
+    object_id: u32,               // see Shape::assign_ids
+    material_id: u32,
+    material: &'a Material,
 }
 
 fn main() {
-    if W % THREAD_COUNT as u32 != 0 {
-        panic!("W must be divisible by THREAD_COUNT")
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, usage());
+            process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", usage());
+        return;
+    }
+    if options.bench {
+        run_benchmark(&options);
+        return;
     }
 
-    let mut img: RgbImage = ImageBuffer::new(W, H);
-    let to_sun = Vec3::new(1.0, 3.0, 2.0);
-    let camera_center = Vec3::new(0.0, 0.0, 1.0);
-
-    println!("size: {} * {}", W, H);
+    let (width, height) = (options.width, options.height);
+    // linear radiance, tone mapped once the render is done
+    let mut hdr: Rgb32FImage = ImageBuffer::new(width, height);
+    let mut aovs: Vec<(Aov, Rgb32FImage)> = options
+        .aovs
+        .iter()
+        .map(|&aov| (aov, ImageBuffer::new(width, height)))
+        .collect();
+
+    println!("size: {} * {}", width, height);
+    println!("seed: {}", options.seed);
     let start = Instant::now();
 
-    let env = make_env(CURRENT_ENV);
-    let lock = Arc::new(env);
+    let scene = match &options.scene_file {
+        Some(path) => read_scene(path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }),
+        None => make_env(options.env),
+    };
+    if let Some(path) = &options.save_scene {
+        if let Err(e) = fs::write(path, write_scene(&scene)) {
+            eprintln!("error: failed to write {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    let lock = Arc::new(scene);
 
     let mut handles = vec![];
-    for i in 0..THREAD_COUNT {
+    for i in 0..options.threads {
         let clone_lock = Arc::clone(&lock);
+        let options = options.clone();
+        // in u64, the products can pass u32::MAX for wide images and many threads
+        let column = |i: u32| (i as u64 * width as u64 / options.threads as u64) as u32;
+        let (w_start, w_end) = (column(i), column(i + 1));
         handles.push(thread::spawn(move || {
-            render(i, to_sun, camera_center, clone_lock)
+            let part_img = render(w_start, w_end, &options, clone_lock);
+            (w_start, part_img)
         }));
     }
 
-    let mut i = 0;
     for handle in handles {
-        let part_img = handle.join().unwrap();
-
-        let w_interval = W / THREAD_COUNT;
-        let w_start = i * w_interval;
-        for w in 0..w_interval {
-            for h in 0..H {
-                img.put_pixel(w + w_start, h, *part_img.get_pixel(w, h));
+        let (w_start, (part_img, part_aovs)) = handle.join().unwrap();
+        for w in 0..part_img.width() {
+            for h in 0..height {
+                hdr.put_pixel(w + w_start, h, *part_img.get_pixel(w, h));
+                for ((_, img), part) in aovs.iter_mut().zip(&part_aovs) {
+                    img.put_pixel(w + w_start, h, *part.get_pixel(w, h));
+                }
             }
         }
-
-        i += 1;
     }
 
-    img.save("asdf.png").unwrap();
+    let saved = if aovs.is_empty() {
+        save_image(&hdr, &options.output, &options)
+    } else {
+        save_aovs(&hdr, &aovs, &options)
+    };
+    if let Err(e) = saved {
+        eprintln!("error: failed to write {}", e);
+        process::exit(1);
+    }
 
     let duration = start.elapsed();
     println!("time: {:?}", duration);
 }
 
-fn render(i: u32, to_sun: Vec3, camera_center: Vec3, env: Arc<Shape>) -> RgbImage {
-    let mut img: RgbImage = ImageBuffer::new(W / THREAD_COUNT, H);
-
-    let w_interval = W / THREAD_COUNT;
-    let w_start = i * w_interval;
-    for w in 0..w_interval {
-        for h in 0..H {
-            let mut color_sum = [0.0, 0.0, 0.0];
-            for _ in 0..SAMPLE_NUMBER {
-                let dx = rand::random::<f32>();
-                let dy = rand::random::<f32>();
-                let pixel_pos = Vec3::new(
-                    ((w + w_start) as f32 + dx) / H as f32 - 0.5 * W as f32 / H as f32,
-                    -((h as f32 + dy) / H as f32 - 0.5),
-                    0.0,
+// the columns w_start..w_end of the image and of each of options.aovs
+fn render(
+    w_start: u32,
+    w_end: u32,
+    options: &Options,
+    scene: Arc<Scene>,
+) -> (Rgb32FImage, Vec<Rgb32FImage>) {
+    let mut img: Rgb32FImage = ImageBuffer::new(w_end - w_start, options.height);
+    let mut aov_imgs: Vec<Rgb32FImage> = options
+        .aovs
+        .iter()
+        .map(|_| ImageBuffer::new(w_end - w_start, options.height))
+        .collect();
+    let cone = scene.camera.pixel_cone(options.height);
+
+    for w in 0..w_end - w_start {
+        // seeded per column so that the image does not depend on the thread count
+        let column = (w + w_start) as u64;
+        let mut rng = StdRng::seed_from_u64(options.seed ^ column.wrapping_mul(0x9e3779b97f4a7c15));
+        for h in 0..options.height {
+            let mut color_sum = Color::black();
+            let mut aov_sums = vec![[0.0; 3]; options.aovs.len()];
+            for sample in 0..options.samples {
+                let dx = rng.gen::<f32>();
+                let dy = rng.gen::<f32>();
+                let ray = scene.camera.generate_ray(
+                    (w + w_start) as f32 + dx,
+                    h as f32 + dy,
+                    options.width,
+                    options.height,
+                    &mut rng,
                 );
-
-                let ray = Ray {
-                    pos: camera_center,
-                    dir: (pixel_pos - camera_center).normalize(),
-                };
-
-                if let Some(info) = env.intersect(ray) {
-                    let sun_pos = info.pos;
-                    let sun_ray = Ray {
-                        pos: sun_pos + 0.00001 * to_sun,
-                        dir: to_sun,
-                    };
-                    if let None = env.intersect(sun_ray) {
-                        let intensity = info.normal.angle(to_sun).cos().clamp(0.0, 1.0);
-                        let color = match info.material {
-                            Material::Simple { color } => color,
-                            Material::Checkerboard {
-                                color1,
-                                color2,
-                                scale,
-                            } => {
-                                let local_pos = info.local_frame.invert().unwrap()
-                                    * Vec4::from_vec3(info.pos, 1.0);
-                                if ((local_pos.x / scale).round() as i32
-                                    + (local_pos.y / scale).round() as i32
-                                    + (local_pos.z / scale).round() as i32)
-                                    % 2
-                                    == 0
-                                {
-                                    color1
-                                } else {
-                                    color2
-                                }
+                if !options.aovs.is_empty() {
+                    let hit = scene.root.intersect(ray).map(|mut info| {
+                        prepare_shading(&mut info, ray.dir.normalize(), cone);
+                        info
+                    });
+                    for (aov, sum) in options.aovs.iter().zip(&mut aov_sums) {
+                        if aov.is_averaged() || sample == 0 {
+                            let value = aov.value(hit.as_ref());
+                            for i in 0..3 {
+                                sum[i] += value[i];
                             }
-                        };
-                        for i in 0..3 {
-                            color_sum[i] += color[i] as f32 * intensity;
                         }
                     }
                 }
+                color_sum +=
+                    options
+                        .integrator
+                        .trace(&scene, ray, cone, options.max_depth, &mut rng);
+            }
+            let c = color_sum / options.samples as f32;
+            img.put_pixel(w, h, Rgb([c.r, c.g, c.b]));
+            for ((aov, sum), aov_img) in options.aovs.iter().zip(aov_sums).zip(&mut aov_imgs) {
+                let n = if aov.is_averaged() {
+                    options.samples as f32
+                } else {
+                    1.0
+                };
+                aov_img.put_pixel(w, h, Rgb(sum.map(|v| v / n)));
             }
-            img.put_pixel(
-                w,
-                h,
-                Rgb(color_sum.map(|i| (i / SAMPLE_NUMBER as f32) as u8)),
-            );
         }
     }
 
-    img
+    (img, aov_imgs)
 }
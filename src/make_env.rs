@@ -1,29 +1,47 @@
 use crate::*;
 use std::f32::consts::PI;
+use std::path::Path;
 
+#[derive(Clone, Copy, Debug)]
 pub enum Env {
     Default,
     Teapot,
 }
 
-pub fn make_env(name: Env) -> Shape {
-    match name {
+impl Env {
+    pub const NAMES: [&'static str; 2] = ["default", "teapot"];
+
+    pub fn from_name(name: &str) -> Option<Env> {
+        match name {
+            "default" => Some(Env::Default),
+            "teapot" => Some(Env::Teapot),
+            _ => None,
+        }
+    }
+}
+
+pub fn make_env(name: Env) -> Scene {
+    let root = match name {
         Env::Default => {
             let mat_s1 = Material::Simple {
-                color: Rgb([255, 0, 0]),
+                color: Texture::constant(Color::from_rgb(Rgb([255, 0, 0]))),
             };
             let mat_s2 = Material::Simple {
-                color: Rgb([0, 255, 0]),
+                color: Texture::constant(Color::from_rgb(Rgb([0, 255, 0]))),
             };
-            let mat_c1 = Material::Checkerboard {
-                color1: Rgb([255, 255, 0]),
-                color2: Rgb([0, 255, 255]),
-                scale: 0.333333,
+            let mat_c1 = Material::Simple {
+                color: Texture::Checker {
+                    color1: Color::from_rgb(Rgb([255, 255, 0])),
+                    color2: Color::from_rgb(Rgb([0, 255, 255])),
+                    scale: 0.333333,
+                },
             };
-            let mat_p1 = Material::Checkerboard {
-                color1: Rgb([255, 255, 255]),
-                color2: Rgb([127, 127, 127]),
-                scale: 1.0,
+            let mat_p1 = Material::Simple {
+                color: Texture::Checker {
+                    color1: Color::from_rgb(Rgb([255, 255, 255])),
+                    color2: Color::from_rgb(Rgb([127, 127, 127])),
+                    scale: 1.0,
+                },
             };
 
             let s1 = Shape::new(mat_s1, Transform::default(), Mesh::Sphere { radius: 1.0 });
@@ -49,45 +67,43 @@ pub fn make_env(name: Env) -> Shape {
             );
             let env_shapes = Shape::new(
                 Material::Simple {
-                    color: Rgb([0, 0, 0]),
+                    color: Texture::constant(Color::from_rgb(Rgb([0, 0, 0]))),
                 },
                 Transform::from_trs(
                     Vec3::new(0.0, 0.0, -3.0),
                     Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), PI / 4.0),
                     Vec3::new(0.7, 1.0, 1.0),
                 ),
-                Mesh::CompositeShape {
-                    shapes: vec![s1, s2, c1],
-                },
+                Mesh::composite(vec![s1, s2, c1]),
             );
             Shape::new(
                 Material::Simple {
-                    color: Rgb([0, 0, 0]),
+                    color: Texture::constant(Color::from_rgb(Rgb([0, 0, 0]))),
                 },
                 Transform::default(),
-                Mesh::CompositeShape {
-                    shapes: vec![p1, env_shapes],
-                },
+                Mesh::composite(vec![p1, env_shapes]),
             )
         }
         Env::Teapot => {
-            let mat_p = Material::Checkerboard {
-                color1: Rgb([0, 255, 255]),
-                color2: Rgb([0, 127, 127]),
-                scale: 1.0,
+            let mat_p = Material::Simple {
+                color: Texture::Checker {
+                    color1: Color::from_rgb(Rgb([0, 255, 255])),
+                    color2: Color::from_rgb(Rgb([0, 127, 127])),
+                    scale: 1.0,
+                },
             };
             let mat_teapot = Material::Simple {
-                color: Rgb([0, 0, 0]),
+                color: Texture::constant(Color::from_rgb(Rgb([0, 0, 0]))),
             };
 
             let p = Shape::new(mat_p, Transform::default(), Mesh::InfinitePlane);
             let teapot = Shape::new(
                 Material::Simple {
-                    color: Rgb([255, 255, 255]),
+                    color: Texture::constant(Color::from_rgb(Rgb([255, 255, 255]))),
                 },
                 Transform::default(),
                 Mesh::Polygons {
-                    obj: read_obj(String::from("teapot")),
+                    obj: Arc::new(read_obj(Path::new(MODELS_DIR), String::from("teapot")).unwrap()),
                 },
             );
             Shape::new(
@@ -96,10 +112,9 @@ pub fn make_env(name: Env) -> Shape {
                     Vec3::new(0.0, -0.8, -6.0),
                     Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), PI / 4.0),
                 ),
-                Mesh::CompositeShape {
-                    shapes: vec![teapot, p],
-                },
+                Mesh::composite(vec![teapot, p]),
             )
         }
-    }
+    };
+    Scene::new(root)
 }
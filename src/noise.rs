@@ -0,0 +1,176 @@
+use std::f32::consts::PI;
+
+use crate::*;
+
+// octaves of the turbulence behind marble and wood
+const PATTERN_OCTAVES: u32 = 5;
+
+// Solid noise patterns with values in [0, 1], evaluated at a point in the local
+// space of a shape so that they stick to it when it moves. Features are about 1
+// unit wide.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    // smoothly interpolated random values
+    Value,
+    // gradient noise
+    Perlin,
+    // distance to the nearest of randomly scattered points, one per unit cell
+    Worley,
+    // fractal Brownian motion, octaves of Perlin noise doubling in frequency
+    Fbm { octaves: u32 },
+    // veins along x bent by turbulence
+    Marble,
+    // rings around the y axis, with a grain stretched along it
+    Wood,
+}
+
+impl Pattern {
+    // names in scene files, where value noise is plain `noise`
+    pub const NAMES: [&'static str; 6] = ["noise", "perlin", "worley", "fbm", "marble", "wood"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Value => "noise",
+            Pattern::Perlin => "perlin",
+            Pattern::Worley => "worley",
+            Pattern::Fbm { .. } => "fbm",
+            Pattern::Marble => "marble",
+            Pattern::Wood => "wood",
+        }
+    }
+
+    pub fn eval(&self, p: Vec3) -> f32 {
+        match self {
+            Pattern::Value => value_noise(p),
+            Pattern::Perlin => (0.5 + 0.5 * perlin(p)).clamp(0.0, 1.0),
+            Pattern::Worley => worley(p).min(1.0),
+            Pattern::Fbm { octaves } => (0.5 + 0.5 * fbm(p, *octaves)).clamp(0.0, 1.0),
+            Pattern::Marble => {
+                0.5 - 0.5 * (PI * (p.x + 2.0 * turbulence(p, PATTERN_OCTAVES))).cos()
+            }
+            Pattern::Wood => {
+                let grain = Vec3::new(2.0 * p.x, 0.25 * p.y, 2.0 * p.z);
+                let distance = (p.x * p.x + p.z * p.z).sqrt() + 0.3 * fbm(grain, PATTERN_OCTAVES);
+                // light early wood darkening toward the end of each ring
+                distance.rem_euclid(1.0).powi(3)
+            }
+        }
+    }
+}
+
+// trilinearly interpolated random values at integer lattice points, in [0, 1]
+pub fn value_noise(p: Vec3) -> f32 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let fade = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (fade(p.x - x0), fade(p.y - y0), fade(p.z - z0));
+    let lattice =
+        |dx: i32, dy: i32, dz: i32| unit(hash(x0 as i32 + dx, y0 as i32 + dy, z0 as i32 + dz));
+
+    let x00 = lerp(lattice(0, 0, 0), lattice(1, 0, 0), fx);
+    let x10 = lerp(lattice(0, 1, 0), lattice(1, 1, 0), fx);
+    let x01 = lerp(lattice(0, 0, 1), lattice(1, 0, 1), fx);
+    let x11 = lerp(lattice(0, 1, 1), lattice(1, 1, 1), fx);
+    lerp(lerp(x00, x10, fy), lerp(x01, x11, fy), fz)
+}
+
+// Perlin's improved gradient noise, about in [-1, 1] and 0 at lattice points
+pub fn perlin(p: Vec3) -> f32 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (fx, fy, fz) = (p.x - x0, p.y - y0, p.z - z0);
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (sx, sy, sz) = (fade(fx), fade(fy), fade(fz));
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let h = hash(x0 as i32 + dx, y0 as i32 + dy, z0 as i32 + dz);
+        gradient(h, fx - dx as f32, fy - dy as f32, fz - dz as f32)
+    };
+
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), sx);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), sx);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), sx);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), sx);
+    lerp(lerp(x00, x10, sy), lerp(x01, x11, sy), sz)
+}
+
+// distance to the nearest feature point, with one point at a random place in
+// every unit cell; about in [0, 1]
+pub fn worley(p: Vec3) -> f32 {
+    let (x0, y0, z0) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+    let mut nearest = f32::MAX;
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (x, y, z) = (x0 + dx, y0 + dy, z0 + dz);
+                let h = hash(x, y, z);
+                let point = Vec3::new(
+                    x as f32 + unit(h),
+                    y as f32 + unit(h.wrapping_mul(0x9e3779b9)),
+                    z as f32 + unit(h.wrapping_mul(0x85ebca6b)),
+                );
+                nearest = nearest.min((point - p).length());
+            }
+        }
+    }
+    nearest
+}
+
+// octaves of Perlin noise, each at twice the frequency and half the amplitude of
+// the previous one, normalized to about [-1, 1]
+pub fn fbm(p: Vec3, octaves: u32) -> f32 {
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(frequency * p);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+// fbm of the absolute value of Perlin noise, in about [0, 1]
+pub fn turbulence(p: Vec3, octaves: u32) -> f32 {
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(frequency * p).abs();
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+// dot product of the offset from a lattice point with one of the 12 directions
+// toward the edges of a cube, picked by the hash of the point
+fn gradient(h: u32, x: f32, y: f32, z: f32) -> f32 {
+    match h % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6b343)
+        ^ (y as u32).wrapping_mul(0xd8163841)
+        ^ (z as u32).wrapping_mul(0xcb1ab31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1e995);
+    h ^= h >> 15;
+    h
+}
+
+fn unit(h: u32) -> f32 {
+    h as f32 / u32::MAX as f32
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
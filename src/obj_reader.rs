@@ -1,28 +1,41 @@
-use std::{fs::File, io::Read};
+use std::{fs::File, io::Read, path::Path};
 
 use crate::*;
 
-pub fn read_obj(name: String) -> Object {
-    let mut file = File::open(format!("resources/models/{}.obj", name)).expect("file not found");
-    let mut obj = Object::new();
+// where scenes built in code find their models
+pub const MODELS_DIR: &str = "resources/models";
 
+// reads `<dir>/<name>.obj`, errors are "<path>: line N: ..."
+pub fn read_obj(dir: &Path, name: String) -> Result<Object, String> {
+    let path = dir.join(format!("{}.obj", name));
     let mut content = String::new();
-    file.read_to_string(&mut content)
-        .expect("something wrong while reading file");
+    File::open(&path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut obj = Object::new();
+    obj.name = name;
 
     let mut points: Vec<Vec3> = vec![];
     let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<(f32, f32)> = vec![];
 
-    let lines = content.split("\n");
-    for line in lines {
+    for (i, line) in content.lines().enumerate() {
+        let error = |message: String| format!("{}: line {}: {}", path.display(), i + 1, message);
         let split: Vec<&str> = line.split_whitespace().collect();
+        if split.is_empty() {
+            continue;
+        }
+        let float = |index: usize| -> Result<f32, String> {
+            let value = split
+                .get(index)
+                .ok_or_else(|| error(format!("missing value {} of '{}'", index, split[0])))?;
+            value
+                .parse::<f32>()
+                .map_err(|_| error(format!("expected a number, found '{}'", value)))
+        };
         match split[0] {
             "v" => {
-                let point = Vec3::new(
-                    split[1].parse::<f32>().unwrap(),
-                    split[2].parse::<f32>().unwrap(),
-                    split[3].parse::<f32>().unwrap(),
-                );
+                let point = Vec3::new(float(1)?, float(2)?, float(3)?);
                 points.push(point);
                 if obj.bounding_box.0.x > point.x {
                     obj.bounding_box.0.x = point.x;
@@ -44,32 +57,62 @@ pub fn read_obj(name: String) -> Object {
                 }
             }
             "vn" => {
-                normals.push(Vec3::new(
-                    split[1].parse::<f32>().unwrap(),
-                    split[2].parse::<f32>().unwrap(),
-                    split[3].parse::<f32>().unwrap(),
-                ));
+                normals.push(Vec3::new(float(1)?, float(2)?, float(3)?));
+            }
+            "vt" => {
+                let v = if split.len() > 2 { float(2)? } else { 0.0 };
+                uvs.push((float(1)?, v));
             }
             "f" => {
-                let mut polygon = Polygon::default();
-                for i in 1..4 {
-                    let p: Vec<&str> = split[i].split("/").collect();
-                    polygon
-                        .points
-                        .push(points[p[0].parse::<usize>().unwrap() - 1]);
-                    polygon
-                        .normals
-                        .push(normals[p[2].parse::<usize>().unwrap() - 1]);
+                // vertices are v, v/vt, v//vn or v/vt/vn
+                if split.len() < 4 {
+                    return Err(error(String::from("a face needs at least 3 vertices")));
                 }
+                // element `i` of one of the lists, from the 1-based index in a vertex
+                let element = |p: &[&str], i: usize, count: usize| match p.get(i) {
+                    Some(s) if !s.is_empty() => match s.parse::<usize>() {
+                        Ok(n) if n > 0 && n <= count => Ok(Some(n - 1)),
+                        _ => Err(error(format!("invalid index '{}'", s))),
+                    },
+                    _ => Ok(None),
+                };
+                let mut vertices = vec![];
+                for vertex in &split[1..] {
+                    let p: Vec<&str> = vertex.split('/').collect();
+                    let point = element(&p, 0, points.len())?
+                        .ok_or_else(|| error(format!("vertex '{}' has no position", vertex)))?;
+                    let uv = element(&p, 1, uvs.len())?;
+                    let normal = element(&p, 2, normals.len())?;
+                    vertices.push((point, uv, normal));
+                }
+
+                // a fan of triangles around the first vertex
+                for k in 1..vertices.len() - 1 {
+                    let mut polygon = Polygon::default();
+                    for (point, uv, normal) in [vertices[0], vertices[k], vertices[k + 1]] {
+                        polygon.points.push(points[point]);
+                        if let Some(n) = normal {
+                            polygon.normals.push(normals[n]);
+                        }
+                        if let Some(t) = uv {
+                            polygon.uvs.push(uvs[t]);
+                        }
+                    }
 
-                polygon.e1 = polygon.points[1] - polygon.points[0];
-                polygon.e2 = polygon.points[2] - polygon.points[0];
-                polygon.polygon_normal = polygon.e1.cross(polygon.e2);
-                obj.polygons.push(polygon);
+                    if polygon.uvs.len() < 3 {
+                        polygon.uvs.clear();
+                    }
+                    polygon.compute_edges();
+                    if polygon.normals.len() < 3 {
+                        polygon.normals = vec![polygon.polygon_normal.normalize(); 3];
+                    }
+                    obj.polygons.push(polygon);
+                }
             }
             _ => {}
         }
     }
 
-    obj
+    obj.build_bvh();
+    Ok(obj)
 }
@@ -0,0 +1,148 @@
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Layer, LayerAttributes};
+use exr::prelude::{SmallVec, WritableImage};
+use image::codecs::hdr::HdrEncoder;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::*;
+
+// Writes a linear framebuffer in the format of the file extension. OpenEXR and
+// Radiance HDR files keep the linear values, scaled by the exposure; other
+// formats are tone mapped and encoded to sRGB, with 16 bits per channel if
+// `bit_depth` is 16.
+pub fn save_image(hdr: &Rgb32FImage, path: &str, options: &Options) -> Result<(), String> {
+    let scale = options.exposure.exp2();
+    let (width, height) = hdr.dimensions();
+    let extension = Path::new(path)
+        .extension()
+        .map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+    let result = match extension.as_str() {
+        "exr" => {
+            let img: Rgb32FImage = ImageBuffer::from_fn(width, height, |x, y| {
+                Rgb(hdr.get_pixel(x, y).0.map(|v| v * scale))
+            });
+            return write_exr(path, &[ChannelGroup::rgb(&img)]);
+        }
+        "hdr" => {
+            let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+            let pixels: Vec<Rgb<f32>> = hdr.pixels().map(|p| Rgb(p.0.map(|v| v * scale))).collect();
+            HdrEncoder::new(BufWriter::new(file)).encode(&pixels, width as usize, height as usize)
+        }
+        _ => {
+            let display = |x: u32, y: u32| {
+                let p = hdr.get_pixel(x, y);
+                let c = options.tone_map.apply(scale * Color::new(p[0], p[1], p[2]));
+                c.linear_to_srgb()
+            };
+            if options.bit_depth == 16 {
+                let img: ImageBuffer<Rgb<u16>, Vec<u16>> =
+                    ImageBuffer::from_fn(width, height, |x, y| {
+                        let c = display(x, y);
+                        Rgb([c.r, c.g, c.b]
+                            .map(|v| (v * 65535.0).round().clamp(0.0, 65535.0) as u16))
+                    });
+                img.save(path)
+            } else {
+                let img: RgbImage = ImageBuffer::from_fn(width, height, |x, y| {
+                    let c = display(x, y);
+                    Rgb([c.r, c.g, c.b].map(|v| (v * 255.0).round().clamp(0.0, 255.0) as u8))
+                });
+                img.save(path)
+            }
+        }
+    };
+    result.map_err(|e| format!("{}: {}", path, e))
+}
+
+// Writes the passes as `<output stem>.<pass>.exr` next to the image, or with
+// `layers` into the output, which has to be an exr file, as channel groups
+// named after the passes.
+pub fn save_aovs(
+    hdr: &Rgb32FImage,
+    aovs: &[(Aov, Rgb32FImage)],
+    options: &Options,
+) -> Result<(), String> {
+    let path = &options.output;
+    if !options.aov_layers {
+        save_image(hdr, path, options)?;
+        let stem = Path::new(path).with_extension("");
+        for (aov, img) in aovs {
+            let pass_path = format!("{}.{}.exr", stem.display(), aov.name());
+            write_exr(&pass_path, &[ChannelGroup::of_aov("", *aov, img)])?;
+        }
+        return Ok(());
+    }
+
+    let scale = options.exposure.exp2();
+    let (width, height) = hdr.dimensions();
+    let beauty: Rgb32FImage = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb(hdr.get_pixel(x, y).0.map(|v| v * scale))
+    });
+    let mut groups = vec![ChannelGroup::rgb(&beauty)];
+    groups.extend(
+        aovs.iter()
+            .map(|(aov, img)| ChannelGroup::of_aov(aov.name(), *aov, img)),
+    );
+    write_exr(path, &groups)
+}
+
+// channels of an exr image taken from the components of one image
+struct ChannelGroup<'a> {
+    prefix: &'a str,                // joined to the names with a dot, "" for none
+    names: &'static [&'static str], // one per component, in order
+    integer: bool,                  // written as u32, for ids
+    image: &'a Rgb32FImage,
+}
+
+impl<'a> ChannelGroup<'a> {
+    fn rgb(image: &'a Rgb32FImage) -> ChannelGroup<'a> {
+        ChannelGroup {
+            prefix: "",
+            names: &["R", "G", "B"],
+            integer: false,
+            image,
+        }
+    }
+
+    fn of_aov(prefix: &'a str, aov: Aov, image: &'a Rgb32FImage) -> ChannelGroup<'a> {
+        ChannelGroup {
+            prefix,
+            names: aov.channels(),
+            integer: aov.is_id(),
+            image,
+        }
+    }
+}
+
+fn write_exr(path: &str, groups: &[ChannelGroup]) -> Result<(), String> {
+    let (width, height) = groups[0].image.dimensions();
+    let mut channels = vec![];
+    for group in groups {
+        for (i, channel) in group.names.iter().enumerate() {
+            let name = if group.prefix.is_empty() {
+                channel.to_string()
+            } else {
+                format!("{}.{}", group.prefix, channel)
+            };
+            let values = group.image.pixels().map(|p| p[i]);
+            let samples = if group.integer {
+                FlatSamples::U32(values.map(|v| v as u32).collect())
+            } else {
+                FlatSamples::F32(values.collect())
+            };
+            channels.push(AnyChannel::new(name.as_str(), samples));
+        }
+    }
+
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+    exr::prelude::Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|e| format!("{}: {}", path, e))
+}
@@ -0,0 +1,33 @@
+use std::f32::consts::PI;
+
+use crate::*;
+
+// uniform point on the unit disk from two uniform numbers in [0, 1)
+pub fn sample_disk(u1: f32, u2: f32) -> (f32, f32) {
+    let r = u1.sqrt();
+    let theta = 2.0 * PI * u2;
+    (r * theta.cos(), r * theta.sin())
+}
+
+// cosine weighted direction around the z axis, pdf = cos(theta) / pi
+pub fn sample_cosine_hemisphere(u1: f32, u2: f32) -> Vec3 {
+    let (x, y) = sample_disk(u1, u2);
+    Vec3::new(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
+
+// two unit vectors that form an orthonormal basis together with n
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+// maps a direction around the z axis to one around n
+pub fn to_world(local: Vec3, n: Vec3) -> Vec3 {
+    let (t, b) = orthonormal_basis(n);
+    local.x * t + local.y * b + local.z * n
+}
@@ -0,0 +1,28 @@
+use crate::*;
+
+#[derive(Clone)]
+pub struct Scene {
+    pub root: Shape,
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+    pub emitters: Vec<(u32, Light)>, // the emissive shapes in root as lights, by object id
+    pub background: Background,
+}
+
+impl Scene {
+    pub fn new(mut root: Shape) -> Scene {
+        root.assign_ids(&mut 1, &mut vec![]);
+        let mut emitters = vec![];
+        root.collect_emitters(Mat4::identity(), &mut emitters);
+        Scene {
+            root,
+            camera: Camera::default(),
+            lights: vec![Light::Directional {
+                direction: Vec3::new(1.0, 3.0, 2.0),
+                intensity: Color::white(),
+            }],
+            emitters,
+            background: Background::default(),
+        }
+    }
+}
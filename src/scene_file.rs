@@ -0,0 +1,1031 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{is_separator, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::*;
+
+// Scene files are whitespace separated tokens with `#` comments, a camera,
+// lights, a background and one root shape; resources/scenes/README.md describes
+// the format.
+
+pub fn read_scene(path: &str) -> Result<Scene, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse_scene(&content, dir).map_err(|e| format!("{}: {}", path, e))
+}
+
+// paths in the scene are relative to `dir`, the directory of the scene file
+pub fn parse_scene(content: &str, dir: &Path) -> Result<Scene, String> {
+    let mut tokens = Tokens::new(content);
+    let mut files = Files {
+        dir: dir.to_path_buf(),
+        objs: HashMap::new(),
+        images: HashMap::new(),
+    };
+    let mut root = None;
+    let mut camera = Camera::default();
+    let mut lights = vec![];
+    let mut background = Background::default();
+
+    while let Some((token, line)) = tokens.next() {
+        match token.as_str() {
+            "camera" => {
+                tokens.expect("{")?;
+                while !tokens.accept("}") {
+                    let (key, line) = tokens.word("camera property")?;
+                    match key.as_str() {
+                        "projection" => camera.projection = parse_projection(&mut tokens)?,
+                        "position" => camera.position = tokens.vec3()?,
+                        "look_at" => camera.look_at = tokens.vec3()?,
+                        "up" => camera.up = tokens.vec3()?,
+                        "fov" => {
+                            camera.fov = tokens.float()?;
+                            if !(camera.fov > 0.0 && camera.fov < 180.0) {
+                                return Err(format!(
+                                    "line {}: fov must be between 0 and 180 degrees",
+                                    line
+                                ));
+                            }
+                        }
+                        "aspect" => camera.aspect = Some(tokens.positive("aspect")?),
+                        "aperture" => {
+                            camera.aperture = tokens.float()?;
+                            if camera.aperture < 0.0 {
+                                return Err(format!(
+                                    "line {}: aperture must not be negative",
+                                    line
+                                ));
+                            }
+                        }
+                        "focus_distance" => {
+                            camera.focus_distance = Some(tokens.positive("focus_distance")?)
+                        }
+                        _ => {
+                            return Err(format!("line {}: unknown camera property '{}'", line, key))
+                        }
+                    }
+                }
+                // the basis of the camera would be degenerate
+                let forward = camera.look_at - camera.position;
+                if forward.length() == 0.0 {
+                    return Err(format!("line {}: camera look_at equals its position", line));
+                }
+                if forward.cross(camera.up).length() <= 1e-6 * forward.length() * camera.up.length()
+                {
+                    return Err(format!(
+                        "line {}: camera up is parallel to the view direction",
+                        line
+                    ));
+                }
+            }
+            "light" => lights.push(parse_light(&mut tokens)?),
+            "background" => background = parse_background(&mut tokens, &files)?,
+            "shape" => {
+                if root.is_some() {
+                    return Err(format!("line {}: a scene has exactly one root shape", line));
+                }
+                root = Some(parse_shape(&mut tokens, &mut files)?);
+            }
+            _ => return Err(format!("line {}: unexpected '{}'", line, token)),
+        }
+    }
+
+    let mut scene = Scene::new(root.ok_or("scene has no root shape")?);
+    scene.camera = camera;
+    scene.lights = lights;
+    scene.background = background;
+    Ok(scene)
+}
+
+fn parse_projection(tokens: &mut Tokens) -> Result<Projection, String> {
+    let (kind, line) = tokens.word("projection type")?;
+    match kind.as_str() {
+        "perspective" => Ok(Projection::Perspective),
+        "orthographic" => Ok(Projection::Orthographic {
+            height: tokens.positive("orthographic height")?,
+        }),
+        "equirectangular" => Ok(Projection::Equirectangular),
+        _ => Err(format!("line {}: unknown projection '{}'", line, kind)),
+    }
+}
+
+fn parse_background(tokens: &mut Tokens, files: &Files) -> Result<Background, String> {
+    let (kind, line) = tokens.word("background type")?;
+    match kind.as_str() {
+        "solid" => Ok(Background::Solid {
+            color: tokens.linear_color()?,
+        }),
+        "gradient" => Ok(Background::Gradient {
+            horizon: tokens.linear_color()?,
+            zenith: tokens.linear_color()?,
+            ground: tokens.linear_color()?,
+        }),
+        "sky" => Ok(Background::Sky {
+            sun_direction: tokens.vec3()?,
+            turbidity: tokens.float()?,
+            intensity: tokens.float()?,
+        }),
+        "environment" => {
+            let (path, line) = tokens.word("environment map path")?;
+            let mut map = EnvironmentMap::open(&files.dir.join(&path).to_string_lossy())
+                .map_err(|e| format!("line {}: {}", line, e))?;
+            map.path = path;
+            Ok(Background::Environment {
+                map: Arc::new(map),
+                rotation: tokens.float()?,
+                intensity: tokens.float()?,
+            })
+        }
+        _ => Err(format!("line {}: unknown background '{}'", line, kind)),
+    }
+}
+
+fn parse_light(tokens: &mut Tokens) -> Result<Light, String> {
+    let (kind, kind_line) = tokens.word("light type")?;
+    let mut vectors: HashMap<String, Vec3> = HashMap::new();
+    let mut floats: HashMap<String, f32> = HashMap::new();
+    tokens.expect("{")?;
+    while !tokens.accept("}") {
+        let (key, line) = tokens.word("light property")?;
+        match key.as_str() {
+            "direction" | "position" | "corner" | "edge1" | "edge2" | "center" | "intensity"
+            | "radiance" => {
+                vectors.insert(key, tokens.vec3()?);
+            }
+            "inner_angle" | "outer_angle" | "radius" => {
+                floats.insert(key, tokens.float()?);
+            }
+            _ => return Err(format!("line {}: unknown light property '{}'", line, key)),
+        }
+    }
+
+    let line = tokens.line();
+    let vector = |key: &str| {
+        vectors
+            .get(key)
+            .copied()
+            .ok_or(format!("line {}: {} light needs a {}", line, kind, key))
+    };
+    let float = |key: &str| {
+        floats
+            .get(key)
+            .copied()
+            .ok_or(format!("line {}: {} light needs a {}", line, kind, key))
+    };
+    let color = |key: &str| vector(key).map(|v| Color::new(v.x, v.y, v.z));
+    match kind.as_str() {
+        "directional" => Ok(Light::Directional {
+            direction: vector("direction")?,
+            intensity: color("intensity")?,
+        }),
+        "point" => Ok(Light::Point {
+            position: vector("position")?,
+            intensity: color("intensity")?,
+        }),
+        "spot" => Ok(Light::Spot {
+            position: vector("position")?,
+            direction: vector("direction")?,
+            intensity: color("intensity")?,
+            inner_angle: float("inner_angle")?,
+            outer_angle: float("outer_angle")?,
+        }),
+        "rect" => {
+            let (edge1, edge2) = (vector("edge1")?, vector("edge2")?);
+            if edge1.cross(edge2).length() == 0.0 {
+                return Err(format!("line {}: rect light edges must span an area", line));
+            }
+            Ok(Light::Rect {
+                corner: vector("corner")?,
+                edge1,
+                edge2,
+                radiance: color("radiance")?,
+            })
+        }
+        "sphere" => Ok(Light::Sphere {
+            center: vector("center")?,
+            radius: match float("radius")? {
+                radius if radius > 0.0 => radius,
+                _ => return Err(format!("line {}: radius must be positive", line)),
+            },
+            radiance: color("radiance")?,
+        }),
+        _ => Err(format!("line {}: unknown light '{}'", kind_line, kind)),
+    }
+}
+
+// models and images loaded so far, shared by every shape that names them
+struct Files {
+    dir: PathBuf,
+    objs: HashMap<String, Arc<Object>>,
+    images: HashMap<(String, bool), Arc<TextureImage>>, // by path and sRGB decoding
+}
+
+fn parse_shape(tokens: &mut Tokens, files: &mut Files) -> Result<Shape, String> {
+    tokens.expect("{")?;
+    let mut material = Material::Simple {
+        color: Texture::constant(Color::black()),
+    };
+    let mut transform = Transform::default();
+    let mut mesh = None;
+    let mut bump = None;
+
+    while !tokens.accept("}") {
+        let (key, line) = tokens.word("shape property")?;
+        match key.as_str() {
+            "material" => material = parse_material(tokens, files)?,
+            "normal_map" => {
+                bump = Some(Bump::Normal {
+                    map: parse_normal_texture(tokens, files)?,
+                    strength: tokens.float()?,
+                })
+            }
+            "bump_map" => {
+                bump = Some(Bump::Height {
+                    map: parse_scalar_texture(tokens, files)?,
+                    scale: tokens.float()?,
+                })
+            }
+            "translation" => transform.set_translation(tokens.vec3()?),
+            "rotation" => transform.set_rotation(parse_rotation(tokens)?),
+            "scale" => {
+                let line = tokens.line();
+                let scale = tokens.vec3()?;
+                // the transform could not be inverted
+                if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+                    return Err(format!("line {}: scale must not be zero", line));
+                }
+                transform.set_scale(scale);
+            }
+            "mesh" => mesh = Some(parse_mesh(tokens, files)?),
+            _ => return Err(format!("line {}: unknown shape property '{}'", line, key)),
+        }
+    }
+
+    let line = tokens.line();
+    let mesh = mesh.ok_or(format!("line {}: shape has no mesh", line))?;
+    if material.is_emissive() && matches!(mesh, Mesh::InfinitePlane) {
+        return Err(format!(
+            "line {}: a plane cannot be emissive, it could not be sampled as a light",
+            line
+        ));
+    }
+    if bump.is_some() && matches!(mesh, Mesh::CompositeShape { .. }) {
+        return Err(format!(
+            "line {}: a composite shape cannot have a normal or bump map",
+            line
+        ));
+    }
+    let mut shape = Shape::new(material, transform, mesh);
+    shape.set_bump(bump);
+    Ok(shape)
+}
+
+fn parse_material(tokens: &mut Tokens, files: &mut Files) -> Result<Material, String> {
+    let (kind, line) = tokens.word("material type")?;
+    match kind.as_str() {
+        "simple" => Ok(Material::Simple {
+            color: parse_color_texture(tokens, files)?,
+        }),
+        "checkerboard" => Ok(Material::Simple {
+            color: Texture::Checker {
+                color1: tokens.color()?,
+                color2: tokens.color()?,
+                scale: tokens.float()?,
+            },
+        }),
+        "pbr" => Ok(Material::Pbr {
+            color: parse_color_texture(tokens, files)?,
+            metallic: parse_scalar_texture(tokens, files)?,
+            roughness: parse_scalar_texture(tokens, files)?,
+            specular: tokens.float()?,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+        }),
+        "clearcoat" => Ok(Material::Pbr {
+            color: parse_color_texture(tokens, files)?,
+            metallic: parse_scalar_texture(tokens, files)?,
+            roughness: parse_scalar_texture(tokens, files)?,
+            specular: tokens.float()?,
+            clearcoat: tokens.float()?,
+            clearcoat_roughness: tokens.float()?,
+        }),
+        "conductor" => {
+            let (name, line) = tokens.word("conductor name")?;
+            let (eta, k) = match name.as_str() {
+                "custom" => (tokens.linear_color()?, tokens.linear_color()?),
+                _ => conductor_preset(&name).ok_or(format!(
+                    "line {}: unknown conductor '{}', expected custom or one of: {}",
+                    line,
+                    name,
+                    CONDUCTOR_NAMES.join(", ")
+                ))?,
+            };
+            Ok(Material::Conductor {
+                eta,
+                k,
+                roughness: parse_scalar_texture(tokens, files)?,
+            })
+        }
+        "mirror" => Ok(Material::Mirror {
+            color: parse_color_texture(tokens, files)?,
+        }),
+        "glass" => Ok(Material::Glass {
+            color: parse_color_texture(tokens, files)?,
+            ior: parse_ior(tokens)?,
+        }),
+        "rough_glass" => Ok(Material::RoughGlass {
+            color: parse_color_texture(tokens, files)?,
+            ior: parse_ior(tokens)?,
+            roughness: parse_scalar_texture(tokens, files)?,
+        }),
+        "emissive" => Ok(Material::Emissive {
+            color: parse_color_texture(tokens, files)?,
+            strength: tokens.float()?,
+        }),
+        _ => Err(format!("line {}: unknown material '{}'", line, kind)),
+    }
+}
+
+fn parse_color_texture(tokens: &mut Tokens, files: &mut Files) -> Result<Texture, String> {
+    parse_texture(tokens, files, Tokens::color, true)
+}
+
+fn parse_scalar_texture(tokens: &mut Tokens, files: &mut Files) -> Result<Texture, String> {
+    let value = |tokens: &mut Tokens| {
+        let value = tokens.float()?;
+        Ok(Color::new(value, value, value))
+    };
+    parse_texture(tokens, files, value, false)
+}
+
+// normals encoded as colors, which are not sRGB
+fn parse_normal_texture(tokens: &mut Tokens, files: &mut Files) -> Result<Texture, String> {
+    parse_texture(tokens, files, Tokens::data_color, false)
+}
+
+// a texture whose constant values are read by `value`; images are decoded from
+// sRGB if `srgb` is set
+fn parse_texture(
+    tokens: &mut Tokens,
+    files: &mut Files,
+    value: fn(&mut Tokens) -> Result<Color, String>,
+    srgb: bool,
+) -> Result<Texture, String> {
+    if tokens.accept("checker") {
+        Ok(Texture::Checker {
+            color1: value(tokens)?,
+            color2: value(tokens)?,
+            scale: tokens.float()?,
+        })
+    } else if tokens.accept("uv_checker") {
+        Ok(Texture::UvChecker {
+            color1: value(tokens)?,
+            color2: value(tokens)?,
+            scale: tokens.float()?,
+        })
+    } else if let Some(name) = Pattern::NAMES.into_iter().find(|name| tokens.accept(name)) {
+        let (color1, color2, scale) = (value(tokens)?, value(tokens)?, tokens.float()?);
+        let pattern = match name {
+            "noise" => Pattern::Value,
+            "perlin" => Pattern::Perlin,
+            "worley" => Pattern::Worley,
+            "fbm" => {
+                let line = tokens.line();
+                let octaves = tokens.int()?;
+                if !(1..=16).contains(&octaves) {
+                    return Err(format!("line {}: octaves must be in 1..=16", line));
+                }
+                Pattern::Fbm { octaves }
+            }
+            "marble" => Pattern::Marble,
+            _ => Pattern::Wood,
+        };
+        Ok(Texture::Noise {
+            pattern,
+            color1,
+            color2,
+            scale,
+        })
+    } else if tokens.accept("image") {
+        let (path, line) = tokens.word("image path")?;
+        let key = (path, srgb);
+        if !files.images.contains_key(&key) {
+            let mut image = TextureImage::open(&files.dir.join(&key.0).to_string_lossy(), srgb)
+                .map_err(|e| format!("line {}: {}", line, e))?;
+            // written back as it was given
+            image.path = key.0.clone();
+            files.images.insert(key.clone(), Arc::new(image));
+        }
+        let wrap = Wrap::NAMES
+            .into_iter()
+            .find(|name| tokens.accept(name))
+            .map_or(Wrap::Repeat, |name| Wrap::from_name(name).unwrap());
+        Ok(Texture::Image {
+            image: Arc::clone(&files.images[&key]),
+            wrap,
+        })
+    } else {
+        Ok(Texture::constant(value(tokens)?))
+    }
+}
+
+fn parse_ior(tokens: &mut Tokens) -> Result<f32, String> {
+    let (token, line) = tokens.word("an ior")?;
+    let ior = token.parse().ok().or(ior_preset(&token)).ok_or(format!(
+        "line {}: expected an ior or one of water, glass, diamond, found '{}'",
+        line, token
+    ))?;
+    if ior > 0.0 && f32::is_finite(ior) {
+        Ok(ior)
+    } else {
+        Err(format!("line {}: ior must be positive", line))
+    }
+}
+
+fn parse_rotation(tokens: &mut Tokens) -> Result<Quat, String> {
+    let (kind, line) = tokens.word("rotation type")?;
+    match kind.as_str() {
+        "axis_angle" => {
+            let line = tokens.line();
+            let axis = tokens.vec3()?;
+            if axis.length() == 0.0 {
+                return Err(format!("line {}: rotation axis must not be zero", line));
+            }
+            let axis = axis.normalize();
+            let angle = tokens.float()?;
+            Ok(Quat::from_axis_angle(axis, angle.to_radians()))
+        }
+        "quat" => {
+            let line = tokens.line();
+            let q = Quat::new(
+                tokens.float()?,
+                tokens.float()?,
+                tokens.float()?,
+                tokens.float()?,
+            );
+            if q.length() == 0.0 {
+                return Err(format!("line {}: quaternion must not be zero", line));
+            }
+            // one that is normalized up to rounding is kept as written, so that
+            // saved scenes read back the same
+            if (q.length() - 1.0).abs() > 1e-6 {
+                return Ok(q.normalize());
+            }
+            Ok(q)
+        }
+        _ => Err(format!("line {}: unknown rotation '{}'", line, kind)),
+    }
+}
+
+fn parse_mesh(tokens: &mut Tokens, files: &mut Files) -> Result<Mesh, String> {
+    let (kind, line) = tokens.word("mesh type")?;
+    match kind.as_str() {
+        "sphere" => Ok(Mesh::Sphere {
+            radius: tokens.positive("radius")?,
+        }),
+        "cube" => Ok(Mesh::Cube {
+            size: Vec3::new(
+                tokens.positive("size")?,
+                tokens.positive("size")?,
+                tokens.positive("size")?,
+            ),
+        }),
+        "plane" => Ok(Mesh::InfinitePlane),
+        "obj" => {
+            let (name, line) = tokens.word("obj name")?;
+            if name.contains(is_separator) {
+                return Err(format!(
+                    "line {}: obj name '{}' is not a file name",
+                    line, name
+                ));
+            }
+            if !files.objs.contains_key(&name) {
+                // models live beside the scenes, as in resources/
+                let models = files.dir.join("..").join("models");
+                let obj =
+                    read_obj(&models, name.clone()).map_err(|e| format!("line {}: {}", line, e))?;
+                files.objs.insert(name.clone(), Arc::new(obj));
+            }
+            Ok(Mesh::Polygons {
+                obj: Arc::clone(&files.objs[&name]),
+            })
+        }
+        "composite" => {
+            tokens.expect("{")?;
+            let mut shapes = vec![];
+            while !tokens.accept("}") {
+                let (key, line) = tokens.word("shape")?;
+                if key != "shape" {
+                    return Err(format!("line {}: expected 'shape', found '{}'", line, key));
+                }
+                shapes.push(parse_shape(tokens, files)?);
+            }
+            Ok(Mesh::composite(shapes))
+        }
+        _ => Err(format!("line {}: unknown mesh '{}'", line, kind)),
+    }
+}
+
+pub fn write_scene(scene: &Scene) -> String {
+    let mut out = String::new();
+    writeln!(out, "camera {{").unwrap();
+    let camera = &scene.camera;
+    match camera.projection {
+        Projection::Perspective => {}
+        Projection::Orthographic { height } => {
+            writeln!(out, "    projection orthographic {}", height).unwrap()
+        }
+        Projection::Equirectangular => writeln!(out, "    projection equirectangular").unwrap(),
+    }
+    writeln!(out, "    position {}", fmt_vec3(camera.position)).unwrap();
+    writeln!(out, "    look_at {}", fmt_vec3(camera.look_at)).unwrap();
+    writeln!(out, "    up {}", fmt_vec3(camera.up)).unwrap();
+    writeln!(out, "    fov {}", camera.fov).unwrap();
+    if let Some(aspect) = camera.aspect {
+        writeln!(out, "    aspect {}", aspect).unwrap();
+    }
+    if camera.aperture > 0.0 {
+        writeln!(out, "    aperture {}", camera.aperture).unwrap();
+    }
+    if let Some(focus_distance) = camera.focus_distance {
+        writeln!(out, "    focus_distance {}", focus_distance).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    for light in &scene.lights {
+        write_light(&mut out, light);
+    }
+    match &scene.background {
+        Background::Solid { color } => {
+            if *color != Color::black() {
+                writeln!(out, "background solid {}", fmt_linear(*color)).unwrap();
+            }
+        }
+        Background::Gradient {
+            horizon,
+            zenith,
+            ground,
+        } => writeln!(
+            out,
+            "background gradient {} {} {}",
+            fmt_linear(*horizon),
+            fmt_linear(*zenith),
+            fmt_linear(*ground)
+        )
+        .unwrap(),
+        Background::Sky {
+            sun_direction,
+            turbidity,
+            intensity,
+        } => writeln!(
+            out,
+            "background sky {} {} {}",
+            fmt_vec3(*sun_direction),
+            turbidity,
+            intensity
+        )
+        .unwrap(),
+        Background::Environment {
+            map,
+            rotation,
+            intensity,
+        } => writeln!(
+            out,
+            "background environment {} {} {}",
+            map.path, rotation, intensity
+        )
+        .unwrap(),
+    }
+    write_shape(&mut out, &scene.root, 0);
+    out
+}
+
+fn write_light(out: &mut String, light: &Light) {
+    let (kind, properties) = match light {
+        Light::Directional {
+            direction,
+            intensity,
+        } => (
+            "directional",
+            vec![
+                ("direction", fmt_vec3(*direction)),
+                ("intensity", fmt_linear(*intensity)),
+            ],
+        ),
+        Light::Point {
+            position,
+            intensity,
+        } => (
+            "point",
+            vec![
+                ("position", fmt_vec3(*position)),
+                ("intensity", fmt_linear(*intensity)),
+            ],
+        ),
+        Light::Spot {
+            position,
+            direction,
+            intensity,
+            inner_angle,
+            outer_angle,
+        } => (
+            "spot",
+            vec![
+                ("position", fmt_vec3(*position)),
+                ("direction", fmt_vec3(*direction)),
+                ("intensity", fmt_linear(*intensity)),
+                ("inner_angle", inner_angle.to_string()),
+                ("outer_angle", outer_angle.to_string()),
+            ],
+        ),
+        Light::Rect {
+            corner,
+            edge1,
+            edge2,
+            radiance,
+        } => (
+            "rect",
+            vec![
+                ("corner", fmt_vec3(*corner)),
+                ("edge1", fmt_vec3(*edge1)),
+                ("edge2", fmt_vec3(*edge2)),
+                ("radiance", fmt_linear(*radiance)),
+            ],
+        ),
+        Light::Sphere {
+            center,
+            radius,
+            radiance,
+        } => (
+            "sphere",
+            vec![
+                ("center", fmt_vec3(*center)),
+                ("radius", radius.to_string()),
+                ("radiance", fmt_linear(*radiance)),
+            ],
+        ),
+        // written as the emissive shape it comes from
+        Light::Mesh { .. } => return,
+    };
+
+    writeln!(out, "light {} {{", kind).unwrap();
+    for (key, value) in properties {
+        writeln!(out, "    {} {}", key, value).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+}
+
+fn write_shape(out: &mut String, shape: &Shape, depth: usize) {
+    let indent = "    ".repeat(depth);
+    writeln!(out, "{}shape {{", indent).unwrap();
+
+    let material = match shape.material() {
+        Material::Simple { color } => format!("simple {}", fmt_color_texture(color)),
+        Material::Pbr {
+            color,
+            metallic,
+            roughness,
+            specular,
+            clearcoat,
+            clearcoat_roughness,
+        } => {
+            let base = format!(
+                "{} {} {} {}",
+                fmt_color_texture(color),
+                fmt_scalar_texture(metallic),
+                fmt_scalar_texture(roughness),
+                specular
+            );
+            if *clearcoat > 0.0 {
+                format!("clearcoat {} {} {}", base, clearcoat, clearcoat_roughness)
+            } else {
+                format!("pbr {}", base)
+            }
+        }
+        Material::Conductor { eta, k, roughness } => format!(
+            "conductor custom {} {} {}",
+            fmt_linear(*eta),
+            fmt_linear(*k),
+            fmt_scalar_texture(roughness)
+        ),
+        Material::Mirror { color } => format!("mirror {}", fmt_color_texture(color)),
+        Material::Glass { color, ior } => format!("glass {} {}", fmt_color_texture(color), ior),
+        Material::RoughGlass {
+            color,
+            ior,
+            roughness,
+        } => format!(
+            "rough_glass {} {} {}",
+            fmt_color_texture(color),
+            ior,
+            fmt_scalar_texture(roughness)
+        ),
+        Material::Emissive { color, strength } => {
+            format!("emissive {} {}", fmt_color_texture(color), strength)
+        }
+    };
+    writeln!(out, "{}    material {}", indent, material).unwrap();
+    match shape.bump() {
+        Some(Bump::Normal { map, strength }) => writeln!(
+            out,
+            "{}    normal_map {} {}",
+            indent,
+            fmt_texture(map, fmt_data_color),
+            strength
+        )
+        .unwrap(),
+        Some(Bump::Height { map, scale }) => writeln!(
+            out,
+            "{}    bump_map {} {}",
+            indent,
+            fmt_scalar_texture(map),
+            scale
+        )
+        .unwrap(),
+        None => {}
+    }
+
+    let transform = shape.transform();
+    let (t, r, s) = (
+        transform.translation(),
+        transform.rotation(),
+        transform.scale(),
+    );
+    if t.x != 0.0 || t.y != 0.0 || t.z != 0.0 {
+        writeln!(out, "{}    translation {}", indent, fmt_vec3(t)).unwrap();
+    }
+    if r.x != 0.0 || r.y != 0.0 || r.z != 0.0 || r.w != 1.0 {
+        writeln!(
+            out,
+            "{}    rotation quat {} {} {} {}",
+            indent, r.x, r.y, r.z, r.w
+        )
+        .unwrap();
+    }
+    if s.x != 1.0 || s.y != 1.0 || s.z != 1.0 {
+        writeln!(out, "{}    scale {}", indent, fmt_vec3(s)).unwrap();
+    }
+
+    match shape.mesh() {
+        Mesh::Sphere { radius } => writeln!(out, "{}    mesh sphere {}", indent, radius).unwrap(),
+        Mesh::Cube { size } => {
+            writeln!(out, "{}    mesh cube {}", indent, fmt_vec3(*size)).unwrap()
+        }
+        Mesh::InfinitePlane => writeln!(out, "{}    mesh plane", indent).unwrap(),
+        Mesh::Polygons { obj } => writeln!(out, "{}    mesh obj {}", indent, obj.name).unwrap(),
+        Mesh::CompositeShape { shapes, .. } => {
+            writeln!(out, "{}    mesh composite {{", indent).unwrap();
+            for child in shapes {
+                write_shape(out, child, depth + 2);
+            }
+            writeln!(out, "{}    }}", indent).unwrap();
+        }
+    }
+
+    writeln!(out, "{}}}", indent).unwrap();
+}
+
+fn fmt_vec3(v: Vec3) -> String {
+    format!("{} {} {}", v.x, v.y, v.z)
+}
+
+fn fmt_linear(c: Color) -> String {
+    format!("{} {} {}", c.r, c.g, c.b)
+}
+
+fn fmt_color(c: Color) -> String {
+    let Rgb([r, g, b]) = c.to_rgb();
+    format!("{} {} {}", r, g, b)
+}
+
+fn fmt_data_color(c: Color) -> String {
+    let channel = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    format!("{} {} {}", channel(c.r), channel(c.g), channel(c.b))
+}
+
+fn fmt_color_texture(texture: &Texture) -> String {
+    fmt_texture(texture, fmt_color)
+}
+
+fn fmt_scalar_texture(texture: &Texture) -> String {
+    fmt_texture(texture, |c| c.r.to_string())
+}
+
+fn fmt_texture(texture: &Texture, value: fn(Color) -> String) -> String {
+    match texture {
+        Texture::Constant { color } => value(*color),
+        Texture::Checker {
+            color1,
+            color2,
+            scale,
+        } => format!("checker {} {} {}", value(*color1), value(*color2), scale),
+        Texture::UvChecker {
+            color1,
+            color2,
+            scale,
+        } => format!("uv_checker {} {} {}", value(*color1), value(*color2), scale),
+        Texture::Noise {
+            pattern,
+            color1,
+            color2,
+            scale,
+        } => {
+            let base = format!(
+                "{} {} {} {}",
+                pattern.name(),
+                value(*color1),
+                value(*color2),
+                scale
+            );
+            match pattern {
+                Pattern::Fbm { octaves } => format!("{} {}", base, octaves),
+                _ => base,
+            }
+        }
+        Texture::Image { image, wrap } => format!("image {} {}", image.path, wrap.name()),
+    }
+}
+
+struct Tokens {
+    tokens: Vec<(String, usize)>, // (token, line number)
+    pos: usize,
+}
+
+impl Tokens {
+    fn new(content: &str) -> Tokens {
+        let mut tokens = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line_content = line.split('#').next().unwrap_or("");
+            let spaced = line_content.replace('{', " { ").replace('}', " } ");
+            for token in spaced.split_whitespace() {
+                tokens.push((token.to_string(), i + 1));
+            }
+        }
+        Tokens { tokens, pos: 0 }
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some((_, line)) => *line,
+            None => self.tokens.last().map_or(0, |(_, line)| *line),
+        }
+    }
+
+    fn next(&mut self) -> Option<(String, usize)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn accept(&mut self, expected: &str) -> bool {
+        if self
+            .tokens
+            .get(self.pos)
+            .is_some_and(|(t, _)| t == expected)
+        {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self, what: &str) -> Result<(String, usize), String> {
+        self.next()
+            .ok_or(format!("unexpected end of file, expected {}", what))
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let (token, line) = self.word(&format!("'{}'", expected))?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(format!(
+                "line {}: expected '{}', found '{}'",
+                line, expected, token
+            ))
+        }
+    }
+
+    fn float(&mut self) -> Result<f32, String> {
+        let (token, line) = self.word("a number")?;
+        match token.parse::<f32>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(format!(
+                "line {}: expected a number, found '{}'",
+                line, token
+            )),
+        }
+    }
+
+    fn positive(&mut self, what: &str) -> Result<f32, String> {
+        let line = self.line();
+        let value = self.float()?;
+        if value > 0.0 {
+            Ok(value)
+        } else {
+            Err(format!("line {}: {} must be positive", line, what))
+        }
+    }
+
+    fn int(&mut self) -> Result<u32, String> {
+        let (token, line) = self.word("an integer")?;
+        token
+            .parse()
+            .map_err(|_| format!("line {}: expected an integer, found '{}'", line, token))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn linear_color(&mut self) -> Result<Color, String> {
+        Ok(Color::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    // an sRGB color
+    fn color(&mut self) -> Result<Color, String> {
+        Ok(Color::from_rgb(self.rgb()?))
+    }
+
+    // three values in 0..=255 that are not sRGB, such as normals
+    fn data_color(&mut self) -> Result<Color, String> {
+        let Rgb([r, g, b]) = self.rgb()?;
+        Ok(Color::new(r as f32, g as f32, b as f32) / 255.0)
+    }
+
+    fn rgb(&mut self) -> Result<Rgb<u8>, String> {
+        let mut color = [0; 3];
+        for channel in color.iter_mut() {
+            let (token, line) = self.word("a color channel")?;
+            *channel = token.parse().map_err(|_| {
+                format!(
+                    "line {}: expected a color channel in 0..=255, found '{}'",
+                    line, token
+                )
+            })?;
+        }
+        Ok(Rgb(color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENES: &str = "resources/scenes";
+
+    fn error(content: &str) -> String {
+        match parse_scene(content, Path::new(SCENES)) {
+            Ok(_) => panic!("parsed {:?}", content),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn bundled_scenes_round_trip() {
+        let mut count = 0;
+        for entry in fs::read_dir(SCENES).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "scene") {
+                continue;
+            }
+            let scene = read_scene(path.to_str().unwrap()).unwrap();
+            let written = write_scene(&scene);
+            // saved next to the original, so the paths still resolve
+            let reparsed = parse_scene(&written, Path::new(SCENES))
+                .unwrap_or_else(|e| panic!("{}: {}\n{}", path.display(), e, written));
+            assert_eq!(write_scene(&reparsed), written, "{}", path.display());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn bad_fov_reports_its_line() {
+        assert_eq!(
+            error("camera {\n    fov 180\n}\nshape { mesh sphere 1 }"),
+            "line 2: fov must be between 0 and 180 degrees"
+        );
+    }
+
+    #[test]
+    fn zero_scale_reports_its_line() {
+        assert_eq!(
+            error("shape {\n    scale 1 0 1\n    mesh sphere 1\n}"),
+            "line 2: scale must not be zero"
+        );
+    }
+
+    #[test]
+    fn missing_obj_reports_its_line() {
+        let e = error("shape {\n    mesh obj missing\n}");
+        assert!(e.starts_with("line 2: "), "{}", e);
+        assert!(e.contains("missing.obj"), "{}", e);
+        assert_eq!(
+            error("shape {\n    mesh obj ../models/teapot\n}"),
+            "line 2: obj name '../models/teapot' is not a file name"
+        );
+    }
+}
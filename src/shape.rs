@@ -1,4 +1,6 @@
+use std::f32::consts::PI;
 use std::mem::swap;
+use std::sync::Arc;
 
 use crate::*;
 
@@ -7,8 +9,19 @@ pub enum Mesh {
     Sphere { radius: f32 },
     Cube { size: Vec3 },
     InfinitePlane,
-    Polygons { obj: Object },
-    CompositeShape { shapes: Vec<Shape> },
+    Polygons { obj: Arc<Object> },
+    CompositeShape { shapes: Vec<Shape>, bvh: Bvh },
+}
+
+impl Mesh {
+    pub fn composite(shapes: Vec<Shape>) -> Mesh {
+        let bounding_boxes: Vec<(Vec3, Vec3)> =
+            shapes.iter().map(|shape| shape.bounding_box()).collect();
+        Mesh::CompositeShape {
+            bvh: Bvh::build(&bounding_boxes),
+            shapes,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -16,6 +29,9 @@ pub struct Shape {
     material: Material,
     transform: Transform,
     mesh: Mesh,
+    bump: Option<Bump>, // unused on composite shapes
+    object_id: u32,
+    material_id: u32,
 }
 
 impl Shape {
@@ -24,18 +40,161 @@ impl Shape {
             material,
             transform,
             mesh,
+            bump: None,
+            object_id: 0,
+            material_id: 0,
         }
     }
 
-    pub fn intersect(&self, ray: Ray) -> Option<Intersection> {
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    pub fn bump(&self) -> Option<&Bump> {
+        self.bump.as_ref()
+    }
+
+    pub fn set_bump(&mut self, bump: Option<Bump>) {
+        self.bump = bump;
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    pub fn intersect(&self, ray: Ray) -> Option<Intersection<'_>> {
         let local_ray = self.transform.inv_transform_ray(ray);
-        match self.intersect_local(local_ray) {
-            Some(intersection) => Some(self.transform.transform_intersection(intersection)),
-            None => None,
+        self.intersect_local(local_ray).map(|mut intersection| {
+            if !matches!(self.mesh, Mesh::CompositeShape { .. }) {
+                intersection.object_id = self.object_id;
+                intersection.material_id = self.material_id;
+                // applied by the integrator once the footprint is known
+                if let Some(bump) = &self.bump {
+                    intersection.bump = Some(bump);
+                    intersection.local_tangents = (intersection.tangent, intersection.bitangent);
+                }
+            }
+            self.transform.transform_intersection(ray, intersection)
+        })
+    }
+
+    // numbers the shapes other than composites from 1 in depth first order, and
+    // their materials so that equal materials share a number; 0 is left for
+    // rays that hit nothing
+    pub fn assign_ids(&mut self, next_id: &mut u32, materials: &mut Vec<Material>) {
+        if let Mesh::CompositeShape { shapes, .. } = &mut self.mesh {
+            for shape in shapes {
+                shape.assign_ids(next_id, materials);
+            }
+            return;
         }
+
+        self.object_id = *next_id;
+        *next_id += 1;
+        self.material_id = match materials.iter().position(|m| *m == self.material) {
+            Some(i) => i as u32 + 1,
+            None => {
+                materials.push(self.material.clone());
+                materials.len() as u32
+            }
+        };
     }
 
-    fn intersect_local(&self, ray: Ray) -> Option<Intersection> {
+    // bounding box in the parent's space, infinite if the shape is unbounded
+    pub fn bounding_box(&self) -> (Vec3, Vec3) {
+        let (min, max) = self.local_bounding_box();
+        if !is_finite_box((min, max)) {
+            return infinite_box();
+        }
+
+        let m = self.transform.matrix();
+        let mut bounding_box = empty_box();
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let p = Vec3::from_vec4(m * Vec4::from_vec3(corner, 1.0));
+            bounding_box = union(bounding_box, (p, p));
+        }
+        bounding_box
+    }
+
+    fn local_bounding_box(&self) -> (Vec3, Vec3) {
+        match &self.mesh {
+            Mesh::Sphere { radius } => (
+                Vec3::new(-radius, -radius, -radius),
+                Vec3::new(*radius, *radius, *radius),
+            ),
+            Mesh::Cube { size } => (-0.5 * *size, 0.5 * *size),
+            Mesh::InfinitePlane => infinite_box(),
+            Mesh::Polygons { obj } => obj.bounding_box,
+            Mesh::CompositeShape { shapes, .. } => {
+                shapes.iter().fold(empty_box(), |bounding_box, shape| {
+                    union(bounding_box, shape.bounding_box())
+                })
+            }
+        }
+    }
+
+    // adds a light with the object id of every emissive shape, in the space that
+    // `parent` maps to; the ids have to be assigned
+    pub fn collect_emitters(&self, parent: Mat4, emitters: &mut Vec<(u32, Light)>) {
+        let m = parent * self.transform.matrix();
+        let mut tolerance = 0.0;
+        let local_triangles = match &self.mesh {
+            Mesh::CompositeShape { shapes, .. } => {
+                for shape in shapes {
+                    shape.collect_emitters(m, emitters);
+                }
+                return;
+            }
+            _ if !self.material.is_emissive() => return,
+            Mesh::Sphere { radius } => {
+                let center = Vec3::from_vec4(m * Vec4::from_vec3(Vec3::new(0.0, 0.0, 0.0), 1.0));
+                let axis = |x, y, z| Vec3::from_vec4(m * Vec4::new(x, y, z, 0.0)).length();
+                let (sx, sy, sz) = (
+                    axis(1.0, 0.0, 0.0),
+                    axis(0.0, 1.0, 0.0),
+                    axis(0.0, 0.0, 1.0),
+                );
+                // still a sphere, sampled exactly
+                if (sx - sy).abs() <= 1e-4 * sx && (sx - sz).abs() <= 1e-4 * sx {
+                    let light = Light::Sphere {
+                        center,
+                        radius: radius * sx,
+                        radiance: self.material.average_emission(),
+                    };
+                    emitters.push((self.object_id, light));
+                    return;
+                }
+                tolerance = f32::INFINITY;
+                sphere_triangles(*radius)
+            }
+            Mesh::Cube { size } => cube_triangles(*size),
+            // rejected by the scene loader, an infinite plane cannot be sampled
+            Mesh::InfinitePlane => return,
+            Mesh::Polygons { obj } => obj
+                .polygons
+                .iter()
+                .map(|p| [p.points[0], p.points[1], p.points[2]])
+                .collect(),
+        };
+
+        let triangles = local_triangles
+            .iter()
+            .map(|triangle| triangle.map(|p| Vec3::from_vec4(m * Vec4::from_vec3(p, 1.0))))
+            .collect();
+        let light = Light::mesh(triangles, self.material.average_emission(), tolerance);
+        emitters.push((self.object_id, light));
+    }
+
+    fn intersect_local(&self, ray: Ray) -> Option<Intersection<'_>> {
         match &self.mesh {
             Mesh::Sphere { radius } => {
                 let a = ray.dir.dot(ray.dir);
@@ -48,6 +207,7 @@ impl Shape {
                     return None;
                 } else if d == 0.0 {
                     t0 = -0.5 * b / a;
+                    t1 = t0;
                 } else {
                     let q = if b > 0.0 {
                         -0.5 * (b + d.sqrt())
@@ -60,16 +220,30 @@ impl Shape {
                         swap(&mut t0, &mut t1);
                     }
                 }
-                if t0 < 0.0 {
+                // from inside the sphere only the far side is ahead
+                let front_face = t0 >= 0.0;
+                let t = if front_face { t0 } else { t1 };
+                if t < 0.0 {
                     None
                 } else {
-                    let intersect_point = ray.pos + t0 * ray.dir;
+                    let intersect_point = ray.pos + t * ray.dir;
+                    let (tangent, bitangent) = sphere_tangents(intersect_point);
                     Some(Intersection {
-                        t: t0,
+                        t,
                         pos: intersect_point,
                         normal: intersect_point.normalize(),
-                        local_frame: Mat4::identity(),
-                        material: self.material,
+                        local_pos: intersect_point,
+                        uv: sphere_uv(intersect_point),
+                        tangent,
+                        bitangent,
+                        uv_density: 1.0 / (PI * radius),
+                        footprint: 0.0,
+                        bump: None,
+                        local_tangents: (Vec3::zero(), Vec3::zero()),
+                        front_face,
+                        object_id: 0,
+                        material_id: 0,
+                        material: &self.material,
                     })
                 }
             }
@@ -88,7 +262,10 @@ impl Shape {
                 if tmax < 0.0 || tmin > tmax {
                     None
                 } else {
-                    let pos = ray.pos + tmin * ray.dir;
+                    // from inside the cube only the exit is ahead
+                    let front_face = tmin >= 0.0;
+                    let t = if front_face { tmin } else { tmax };
+                    let pos = ray.pos + t * ray.dir;
                     let normalized_pos = Vec3::new(
                         if (pos.x / size.x).abs() * 2.0 < 0.99999 {
                             0.0
@@ -107,12 +284,23 @@ impl Shape {
                         },
                     )
                     .normalize();
+                    let (uv, tangent, bitangent) = cube_uv(pos, *size);
                     Some(Intersection {
-                        t: tmin,
+                        t,
                         pos,
                         normal: normalized_pos.normalize(),
-                        local_frame: Mat4::identity(),
-                        material: self.material,
+                        local_pos: pos,
+                        uv,
+                        tangent,
+                        bitangent,
+                        uv_density: 3.0 / (size.x + size.y + size.z),
+                        footprint: 0.0,
+                        bump: None,
+                        local_tangents: (Vec3::zero(), Vec3::zero()),
+                        front_face,
+                        object_id: 0,
+                        material_id: 0,
+                        material: &self.material,
                     })
                 }
             }
@@ -124,107 +312,257 @@ impl Shape {
                 if intersection_t < 0.0 {
                     None
                 } else {
+                    let pos = ray.pos + intersection_t * ray.dir;
                     Some(Intersection {
                         t: intersection_t,
-                        pos: ray.pos + intersection_t * ray.dir,
+                        pos,
                         normal: Vec3::new(0.0, if ray.pos.y > 0.0 { 1.0 } else { -1.0 }, 0.0),
-                        local_frame: Mat4::identity(),
-                        material: self.material,
+                        local_pos: pos,
+                        uv: (pos.x, -pos.z),
+                        tangent: Vec3::new(1.0, 0.0, 0.0),
+                        bitangent: Vec3::new(0.0, 0.0, -1.0),
+                        uv_density: 1.0,
+                        footprint: 0.0,
+                        bump: None,
+                        local_tangents: (Vec3::zero(), Vec3::zero()),
+                        // the side of +y is the outside
+                        front_face: ray.pos.y > 0.0,
+                        object_id: 0,
+                        material_id: 0,
+                        material: &self.material,
                     })
                 }
             }
-            Mesh::Polygons { obj } => {
-                let inv_x = 1.0 / ray.dir.x;
-                let inv_y = 1.0 / ray.dir.y;
-                let inv_z = 1.0 / ray.dir.z;
-                let t1 = (obj.bounding_box.0.x - ray.pos.x) * inv_x;
-                let t2 = (obj.bounding_box.1.x - ray.pos.x) * inv_x;
-                let t3 = (obj.bounding_box.0.y - ray.pos.y) * inv_y;
-                let t4 = (obj.bounding_box.1.y - ray.pos.y) * inv_y;
-                let t5 = (obj.bounding_box.0.z - ray.pos.z) * inv_z;
-                let t6 = (obj.bounding_box.1.z - ray.pos.z) * inv_z;
-                let tmin = t1.min(t2).max(t3.min(t4)).max(t5.min(t6));
-                let tmax = t1.max(t2).min(t3.max(t4)).min(t5.max(t6));
-                if tmax < 0.0 || tmin > tmax {
-                    None
-                } else {
-                    obj.polygons
-                        .iter()
-                        .fold(None, |cur: Option<Intersection>, polygon| {
-                            let p0 = polygon.points[0];
-                            let n0 = polygon.normals[0];
-                            let n1 = polygon.normals[1];
-                            let n2 = polygon.normals[2];
-
-                            let e1 = polygon.e1;
-                            let e2 = polygon.e2;
-                            let s = ray.pos - p0;
-                            let p = ray.dir.cross(e2);
-                            let q = s.cross(e1);
-
-                            let tvw =
-                                1.0 / p.dot(e1) * Vec3::new(q.dot(e2), p.dot(s), q.dot(ray.dir));
-                            let intersection_t = tvw.x;
-                            let w1 = tvw.y;
-                            let w2 = tvw.z;
-                            let w0 = 1.0 - w1 - w2;
-
-                            if intersection_t < 0.0
-                                || w0 < -0.0
-                                || w0 > 1.0
-                                || w1 < -0.0
-                                || w1 > 1.0
-                                || w2 < -0.0
-                                || w2 > 1.0
-                            {
-                                return cur;
-                            }
-
-                            let n = (w0 * n0 + w1 * n1 + w2 * n2).normalize();
-
-                            let info = Intersection {
-                                t: intersection_t,
-                                pos: ray.pos + intersection_t * ray.dir,
-                                normal: if ray.dir.dot(polygon.polygon_normal) <= 0.0 {
-                                    n
-                                } else {
-                                    -1.0 * n
-                                },
-                                local_frame: Mat4::identity(),
-                                material: self.material,
-                            };
-                            match cur {
-                                Some(cur_info) => {
-                                    if info.t < cur_info.t {
-                                        Some(info)
-                                    } else {
-                                        Some(cur_info)
-                                    }
-                                }
-                                None => Some(info),
-                            }
-                        })
-                }
+            Mesh::Polygons { obj } => obj.bvh.traverse(ray, |i| {
+                intersect_polygon(&obj.polygons[i], ray, &self.material)
+            }),
+            Mesh::CompositeShape { shapes, bvh } => bvh.traverse(ray, |i| shapes[i].intersect(ray)),
+        }
+    }
+}
+
+fn intersect_polygon<'a>(
+    polygon: &Polygon,
+    ray: Ray,
+    material: &'a Material,
+) -> Option<Intersection<'a>> {
+    let p0 = polygon.points[0];
+    let n0 = polygon.normals[0];
+    let n1 = polygon.normals[1];
+    let n2 = polygon.normals[2];
+
+    let e1 = polygon.e1;
+    let e2 = polygon.e2;
+    let s = ray.pos - p0;
+    let p = ray.dir.cross(e2);
+    let q = s.cross(e1);
+
+    let tvw = 1.0 / p.dot(e1) * Vec3::new(q.dot(e2), p.dot(s), q.dot(ray.dir));
+    let intersection_t = tvw.x;
+    let w1 = tvw.y;
+    let w2 = tvw.z;
+    let w0 = 1.0 - w1 - w2;
+
+    if intersection_t < 0.0
+        || !(0.0..=1.0).contains(&w0)
+        || !(0.0..=1.0).contains(&w1)
+        || !(0.0..=1.0).contains(&w2)
+    {
+        return None;
+    }
+
+    let n = (w0 * n0 + w1 * n1 + w2 * n2).normalize();
+    let pos = ray.pos + intersection_t * ray.dir;
+    // without texture coordinates in the obj, the barycentrics of the triangle
+    let uv = match polygon.uvs[..] {
+        [(u0, v0), (u1, v1), (u2, v2)] => {
+            (w0 * u0 + w1 * u1 + w2 * u2, w0 * v0 + w1 * v1 + w2 * v2)
+        }
+        _ => (w1, w2),
+    };
+
+    Some(Intersection {
+        t: intersection_t,
+        pos,
+        normal: if ray.dir.dot(polygon.polygon_normal) <= 0.0 {
+            n
+        } else {
+            -1.0 * n
+        },
+        local_pos: pos,
+        uv,
+        tangent: polygon.tangent,
+        bitangent: polygon.bitangent,
+        uv_density: polygon.uv_density,
+        footprint: 0.0,
+        bump: None,
+        local_tangents: (Vec3::zero(), Vec3::zero()),
+        // the side that the winding of the points faces is the outside
+        front_face: ray.dir.dot(polygon.polygon_normal) <= 0.0,
+        object_id: 0,
+        material_id: 0,
+        material,
+    })
+}
+
+// each face is mapped to the unit square, upright when seen from outside with
+// y up, and the top and bottom faces oriented toward -z; returns the uv and
+// its tangent and bitangent
+fn cube_uv(pos: Vec3, size: Vec3) -> ((f32, f32), Vec3, Vec3) {
+    let p = Vec3::new(pos.x / size.x, pos.y / size.y, pos.z / size.z);
+    let (ax, ay, az) = (p.x.abs(), p.y.abs(), p.z.abs());
+    if ax >= ay && ax >= az {
+        (
+            (0.5 - p.x.signum() * p.z, 0.5 + p.y),
+            Vec3::new(0.0, 0.0, -p.x.signum() * size.z),
+            Vec3::new(0.0, size.y, 0.0),
+        )
+    } else if ay >= az {
+        (
+            (0.5 + p.x, 0.5 - p.y.signum() * p.z),
+            Vec3::new(size.x, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -p.y.signum() * size.z),
+        )
+    } else {
+        (
+            (0.5 + p.z.signum() * p.x, 0.5 + p.y),
+            Vec3::new(p.z.signum() * size.x, 0.0, 0.0),
+            Vec3::new(0.0, size.y, 0.0),
+        )
+    }
+}
+
+// longitude and latitude of a point on a sphere around the origin, both in [0, 1]
+fn sphere_uv(p: Vec3) -> (f32, f32) {
+    let p = p.normalize();
+    (
+        0.5 + p.x.atan2(p.z) / (2.0 * PI),
+        0.5 + p.y.clamp(-1.0, 1.0).asin() / PI,
+    )
+}
+
+// derivatives of the point along the u and v of `sphere_uv`
+fn sphere_tangents(p: Vec3) -> (Vec3, Vec3) {
+    let rho = (p.x * p.x + p.z * p.z).sqrt();
+    let tangent = 2.0 * PI * Vec3::new(p.z, 0.0, -p.x);
+    if rho == 0.0 {
+        return (tangent, Vec3::new(0.0, 0.0, 0.0));
+    }
+    let bitangent = PI * Vec3::new(-p.y * p.x / rho, rho, -p.y * p.z / rho);
+    (tangent, bitangent)
+}
+
+fn sphere_triangles(radius: f32) -> Vec<[Vec3; 3]> {
+    const STACKS: usize = 16;
+    const SLICES: usize = 32;
+    let point = |i: usize, j: usize| {
+        let theta = PI * i as f32 / STACKS as f32;
+        let phi = 2.0 * PI * j as f32 / SLICES as f32;
+        radius
+            * Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            )
+    };
+
+    let mut triangles = vec![];
+    for i in 0..STACKS {
+        for j in 0..SLICES {
+            let (p00, p01) = (point(i, j), point(i, j + 1));
+            let (p10, p11) = (point(i + 1, j), point(i + 1, j + 1));
+            // wound to face outward
+            if i != 0 {
+                triangles.push([p00, p01, p10]);
             }
-            Mesh::CompositeShape { shapes } => {
-                shapes
-                    .iter()
-                    .fold(None, |cur: Option<Intersection>, shape| {
-                        match shape.intersect(ray) {
-                            Some(info) => match cur {
-                                Some(cur_info) => {
-                                    if info.t < cur_info.t {
-                                        Some(info)
-                                    } else {
-                                        Some(cur_info)
-                                    }
-                                }
-                                None => Some(info),
-                            },
-                            None => cur,
-                        }
-                    })
+            if i != STACKS - 1 {
+                triangles.push([p01, p11, p10]);
             }
         }
     }
+    triangles
+}
+
+fn cube_triangles(size: Vec3) -> Vec<[Vec3; 3]> {
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 == 0 { -0.5 } else { 0.5 } * size.x,
+            if i & 2 == 0 { -0.5 } else { 0.5 } * size.y,
+            if i & 4 == 0 { -0.5 } else { 0.5 } * size.z,
+        )
+    };
+    // corner indices of each face, wound to face outward
+    let faces = [
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+    ];
+
+    let mut triangles = vec![];
+    for [a, b, c, d] in faces {
+        triangles.push([corner(a), corner(b), corner(c)]);
+        triangles.push([corner(a), corner(c), corner(d)]);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::path::Path;
+
+    // the closest hit over all polygons, without the bvh
+    fn brute_force<'a>(obj: &Object, ray: Ray, material: &'a Material) -> Option<Intersection<'a>> {
+        obj.polygons
+            .iter()
+            .filter_map(|polygon| intersect_polygon(polygon, ray, material))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+
+    #[test]
+    fn bvh_matches_brute_force_on_teapot() {
+        let obj = read_obj(Path::new(MODELS_DIR), String::from("teapot")).unwrap();
+        let material = Material::Simple {
+            color: Texture::constant(Color::white()),
+        };
+        let (min, max) = obj.bounding_box;
+        let center = 0.5 * (min + max);
+        let radius = (max - min).length();
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let dir = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let pos = center + radius * dir.normalize();
+            let target = Vec3::new(
+                rng.gen_range(min.x..max.x),
+                rng.gen_range(min.y..max.y),
+                rng.gen_range(min.z..max.z),
+            );
+            let ray = Ray {
+                pos,
+                dir: (target - pos).normalize(),
+            };
+
+            let bvh_hit = obj
+                .bvh
+                .traverse(ray, |i| intersect_polygon(&obj.polygons[i], ray, &material));
+            let expected = brute_force(&obj, ray, &material);
+            assert_eq!(
+                bvh_hit.as_ref().map(|h| h.t),
+                expected.as_ref().map(|h| h.t)
+            );
+            hits += bvh_hit.is_some() as u32;
+        }
+        assert!(hits > 100, "only {} rays hit the teapot", hits);
+    }
 }
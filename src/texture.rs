@@ -0,0 +1,303 @@
+use image::codecs::hdr::HdrDecoder;
+use image::{ColorType, ImageResult};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::*;
+
+// A color or scalar parameter of a material, evaluated at a hit. Scalar
+// parameters such as roughness read the red channel.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Texture {
+    Constant {
+        color: Color,
+    },
+    // 3D checkerboard in the local space of the shape
+    Checker {
+        color1: Color,
+        color2: Color,
+        scale: f32, // size of a cell
+    },
+    // 2D checkerboard over the texture coordinates
+    UvChecker {
+        color1: Color,
+        color2: Color,
+        scale: f32, // size of a cell in uv units
+    },
+    Image {
+        image: Arc<TextureImage>,
+        wrap: Wrap,
+    },
+    // a noise pattern in the local space of the shape, between two colors
+    Noise {
+        pattern: Pattern,
+        color1: Color,
+        color2: Color,
+        scale: f32, // size of a noise feature
+    },
+}
+
+impl Texture {
+    pub fn constant(color: Color) -> Texture {
+        Texture::Constant { color }
+    }
+
+    pub fn eval(&self, info: &Intersection) -> Color {
+        match self {
+            Texture::Constant { color } => *color,
+            Texture::Checker {
+                color1,
+                color2,
+                scale,
+            } => {
+                let p = info.local_pos / *scale;
+                let parity = p.x.round() as i32 + p.y.round() as i32 + p.z.round() as i32;
+                if parity % 2 == 0 {
+                    *color1
+                } else {
+                    *color2
+                }
+            }
+            Texture::UvChecker {
+                color1,
+                color2,
+                scale,
+            } => {
+                let (u, v) = info.uv;
+                let parity = (u / scale).floor() as i32 + (v / scale).floor() as i32;
+                if parity % 2 == 0 {
+                    *color1
+                } else {
+                    *color2
+                }
+            }
+            Texture::Image { image, wrap } => {
+                image.sample(info.uv, info.footprint * info.uv_density, *wrap)
+            }
+            Texture::Noise {
+                pattern,
+                color1,
+                color2,
+                scale,
+            } => {
+                let t = pattern.eval(info.local_pos / *scale);
+                (1.0 - t) * *color1 + t * *color2
+            }
+        }
+    }
+
+    pub fn scalar(&self, info: &Intersection) -> f32 {
+        self.eval(info).r
+    }
+
+    // mean over the surface, where a single value is needed such as for light sampling
+    pub fn average(&self) -> Color {
+        match self {
+            Texture::Constant { color } => *color,
+            Texture::Checker { color1, color2, .. }
+            | Texture::UvChecker { color1, color2, .. }
+            | Texture::Noise { color1, color2, .. } => 0.5 * (*color1 + *color2),
+            Texture::Image { image, .. } => image.average,
+        }
+    }
+}
+
+// how texture coordinates outside of [0, 1] are mapped into the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    pub const NAMES: [&'static str; 3] = ["repeat", "clamp", "mirror"];
+
+    pub fn from_name(name: &str) -> Option<Wrap> {
+        match name {
+            "repeat" => Some(Wrap::Repeat),
+            "clamp" => Some(Wrap::Clamp),
+            "mirror" => Some(Wrap::Mirror),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wrap::Repeat => "repeat",
+            Wrap::Clamp => "clamp",
+            Wrap::Mirror => "mirror",
+        }
+    }
+
+    // texel index in 0..size
+    fn apply(&self, i: i64, size: usize) -> usize {
+        let n = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Clamp => i.clamp(0, n - 1),
+            Wrap::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+// an image file with its mipmaps, halving down to a single pixel; v goes up
+// from the bottom row. Color images are decoded from sRGB, data such as normals
+// and roughness is read as it is.
+#[derive(Debug)]
+pub struct TextureImage {
+    pub path: String,
+    levels: Vec<MipLevel>,
+    average: Color,
+}
+
+#[derive(Debug)]
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+// images are the same if they are loaded once and shared
+impl PartialEq for TextureImage {
+    fn eq(&self, other: &TextureImage) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl TextureImage {
+    pub fn open(path: &str, srgb: bool) -> Result<TextureImage, String> {
+        let (width, height, pixels) =
+            read_linear_image(path, srgb).map_err(|e| format!("{}: {}", path, e))?;
+        let mut level = MipLevel {
+            width,
+            height,
+            pixels,
+        };
+
+        let mut levels = vec![];
+        while level.width > 1 || level.height > 1 {
+            let next = level.downsample();
+            levels.push(level);
+            level = next;
+        }
+        let average = level.pixels[0];
+        levels.push(level);
+
+        Ok(TextureImage {
+            path: path.to_string(),
+            levels,
+            average,
+        })
+    }
+
+    // trilinear filtering between the two mipmaps whose texels are closest
+    // to `footprint`, the width of the area to cover in uv units
+    fn sample(&self, uv: (f32, f32), footprint: f32, wrap: Wrap) -> Color {
+        let base = &self.levels[0];
+        let texels = footprint * base.width.max(base.height) as f32;
+        let last = (self.levels.len() - 1) as f32;
+        let level = if texels > 1.0 {
+            texels.log2().min(last)
+        } else {
+            0.0
+        };
+
+        let fine = level.floor();
+        let t = level - fine;
+        let color = self.levels[fine as usize].bilinear(uv, wrap);
+        if t <= 0.0 {
+            return color;
+        }
+        (1.0 - t) * color + t * self.levels[fine as usize + 1].bilinear(uv, wrap)
+    }
+}
+
+impl MipLevel {
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> Color {
+        self.pixels[wrap.apply(y, self.height) * self.width + wrap.apply(x, self.width)]
+    }
+
+    fn bilinear(&self, (u, v): (f32, f32), wrap: Wrap) -> Color {
+        // texel centers are at half integers
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = (1.0 - fx) * self.texel(x0, y0, wrap) + fx * self.texel(x0 + 1, y0, wrap);
+        let bottom =
+            (1.0 - fx) * self.texel(x0, y0 + 1, wrap) + fx * self.texel(x0 + 1, y0 + 1, wrap);
+        (1.0 - fy) * top + fy * bottom
+    }
+
+    // box filter of 2 x 2 texels, or of 2 x 1 once one side is down to 1
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, y0) = (2 * x, 2 * y);
+                let x1 = (x0 + 1).min(self.width - 1);
+                let y1 = (y0 + 1).min(self.height - 1);
+                let sum = self.pixels[y0 * self.width + x0]
+                    + self.pixels[y0 * self.width + x1]
+                    + self.pixels[y1 * self.width + x0]
+                    + self.pixels[y1 * self.width + x1];
+                pixels.push(sum / 4.0);
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+// width, height and linear pixels of an image file. Integer images are decoded
+// from sRGB if `srgb` is set, float images are linear already. The generic
+// loader of image converts Radiance files to 8 bits, so those are decoded directly.
+pub fn read_linear_image(path: &str, srgb: bool) -> ImageResult<(usize, usize, Vec<Color>)> {
+    if Path::new(path)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("hdr"))
+    {
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()?
+            .iter()
+            .map(|p| Color::new(p[0], p[1], p[2]))
+            .collect();
+        return Ok((metadata.width as usize, metadata.height as usize, pixels));
+    }
+
+    let image = image::open(path)?;
+    let decode = srgb && !matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    let image = image.into_rgb32f();
+    let pixels = image
+        .pixels()
+        .map(|p| {
+            let c = Color::new(p[0], p[1], p[2]);
+            if decode {
+                c.srgb_to_linear()
+            } else {
+                c
+            }
+        })
+        .collect();
+    Ok((image.width() as usize, image.height() as usize, pixels))
+}
@@ -0,0 +1,45 @@
+use crate::*;
+
+// Maps linear radiance to displayable values in [0, 1], after scaling it by the
+// exposure.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    // cuts off everything above 1
+    Clamp,
+    // L / (1 + L) on the luminance, keeping the hue
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, per channel
+    Aces,
+}
+
+impl ToneMap {
+    pub const NAMES: [&'static str; 3] = ["clamp", "reinhard", "aces"];
+
+    pub fn from_name(name: &str) -> Option<ToneMap> {
+        match name {
+            "clamp" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "aces" => Some(ToneMap::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, c: Color) -> Color {
+        let mapped = match self {
+            ToneMap::Clamp => c,
+            ToneMap::Reinhard => c / (1.0 + c.luminance().max(0.0)),
+            ToneMap::Aces => {
+                let curve = |x: f32| {
+                    let x = x.max(0.0);
+                    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+                };
+                Color::new(curve(c.r), curve(c.g), curve(c.b))
+            }
+        };
+        Color::new(
+            mapped.r.clamp(0.0, 1.0),
+            mapped.g.clamp(0.0, 1.0),
+            mapped.b.clamp(0.0, 1.0),
+        )
+    }
+}
@@ -5,67 +5,122 @@ pub struct Transform {
     translation: Vec3,
     rotation: Quat,
     scale: Vec3,
+    matrix: Mat4,
+    inv_matrix: Mat4,
+    normal_matrix: Mat4, // inverse transpose of matrix
 }
 
-impl Transform {
-    pub fn default() -> Transform {
-        Transform {
-            translation: Vec3::new(0.0, 0.0, 0.0),
-            rotation: Quat::default(),
-            scale: Vec3::new(1.0, 1.0, 1.0),
-        }
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::from_trs(
+            Vec3::new(0.0, 0.0, 0.0),
+            Quat::default(),
+            Vec3::new(1.0, 1.0, 1.0),
+        )
     }
+}
 
+impl Transform {
     pub fn from_t(t: Vec3) -> Transform {
-        Transform {
-            translation: t,
-            rotation: Quat::default(),
-            scale: Vec3::new(1.0, 1.0, 1.0),
-        }
+        Transform::from_trs(t, Quat::default(), Vec3::new(1.0, 1.0, 1.0))
     }
 
     pub fn from_tr(t: Vec3, r: Quat) -> Transform {
-        Transform {
-            translation: t,
-            rotation: r,
-            scale: Vec3::new(1.0, 1.0, 1.0),
-        }
+        Transform::from_trs(t, r, Vec3::new(1.0, 1.0, 1.0))
     }
 
     pub fn from_trs(t: Vec3, r: Quat, s: Vec3) -> Transform {
-        Transform {
+        let mut transform = Transform {
             translation: t,
             rotation: r,
             scale: s,
-        }
+            matrix: Mat4::identity(),
+            inv_matrix: Mat4::identity(),
+            normal_matrix: Mat4::identity(),
+        };
+        transform.update_matrices();
+        transform
     }
 
-    pub fn matrix(&self) -> Mat4 {
+    fn update_matrices(&mut self) {
         let matrix_t = Mat4::from_translation(self.translation);
         let matrix_r = Mat4::from_quat(self.rotation);
         let matrix_s = Mat4::from_scale(self.scale);
 
-        matrix_t * matrix_r * matrix_s
+        self.matrix = matrix_t * matrix_r * matrix_s;
+        self.inv_matrix = self.matrix.invert().unwrap();
+        self.normal_matrix = self.inv_matrix.transpose();
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.translation
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    pub fn set_translation(&mut self, t: Vec3) {
+        self.translation = t;
+        self.update_matrices();
+    }
+
+    pub fn set_rotation(&mut self, r: Quat) {
+        self.rotation = r;
+        self.update_matrices();
+    }
+
+    pub fn set_scale(&mut self, s: Vec3) {
+        self.scale = s;
+        self.update_matrices();
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn inv_matrix(&self) -> Mat4 {
+        self.inv_matrix
     }
 
     pub fn inv_transform_ray(&self, ray: Ray) -> Ray {
-        let m = self.matrix();
-        let m_inv = m.invert().unwrap();
+        let m_inv = self.inv_matrix;
         Ray {
             pos: Vec3::from_vec4(m_inv * Vec4::from_vec3(ray.pos, 1.0)),
             dir: Vec3::from_vec4(m_inv * Vec4::from_vec3(ray.dir, 0.0)),
         }
     }
 
-    pub fn transform_intersection(&self, local_intersection: Intersection) -> Intersection {
-        let m = self.matrix();
+    // `ray` is the ray in the parent space that found the intersection; t is the
+    // same in both spaces
+    pub fn transform_intersection<'a>(
+        &self,
+        ray: Ray,
+        local_intersection: Intersection<'a>,
+    ) -> Intersection<'a> {
+        let m = self.matrix;
         Intersection {
             t: local_intersection.t,
-            pos: Vec3::from_vec4(m * Vec4::from_vec3(local_intersection.pos, 1.0)),
+            pos: ray.pos + local_intersection.t * ray.dir,
             normal: Vec3::from_vec4(
-                m.invert().unwrap().transpose() * Vec4::from_vec3(local_intersection.normal, 0.0),
+                self.normal_matrix * Vec4::from_vec3(local_intersection.normal, 0.0),
             ),
-            local_frame: m * local_intersection.local_frame,
+            local_pos: local_intersection.local_pos,
+            uv: local_intersection.uv,
+            tangent: Vec3::from_vec4(m * Vec4::from_vec3(local_intersection.tangent, 0.0)),
+            bitangent: Vec3::from_vec4(m * Vec4::from_vec3(local_intersection.bitangent, 0.0)),
+            uv_density: local_intersection.uv_density * 3.0
+                / (self.scale.x.abs() + self.scale.y.abs() + self.scale.z.abs()),
+            footprint: local_intersection.footprint,
+            bump: local_intersection.bump,
+            local_tangents: local_intersection.local_tangents,
+            front_face: local_intersection.front_face,
+            object_id: local_intersection.object_id,
+            material_id: local_intersection.material_id,
             material: local_intersection.material,
         }
     }
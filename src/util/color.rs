@@ -0,0 +1,171 @@
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Color {
+    pub fn new(r: f32, g: f32, b: f32) -> Color {
+        Color { r, g, b }
+    }
+
+    pub fn black() -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    pub fn white() -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    pub fn max_component(&self) -> f32 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    // relative luminance of linear sRGB
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    // decodes an 8-bit sRGB color
+    pub fn from_rgb(c: Rgb<u8>) -> Color {
+        Color::new(
+            c[0] as f32 / 255.0,
+            c[1] as f32 / 255.0,
+            c[2] as f32 / 255.0,
+        )
+        .srgb_to_linear()
+    }
+
+    // encodes to sRGB, rounded to the nearest 8-bit value
+    pub fn to_rgb(self) -> Rgb<u8> {
+        let c = self.linear_to_srgb();
+        Rgb([c.r, c.g, c.b].map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8))
+    }
+
+    pub fn srgb_to_linear(self) -> Color {
+        let decode = |v: f32| {
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Color::new(decode(self.r), decode(self.g), decode(self.b))
+    }
+
+    pub fn linear_to_srgb(self) -> Color {
+        let encode = |v: f32| {
+            if v <= 0.0031308 {
+                12.92 * v
+            } else {
+                1.055 * v.powf(1.0 / 2.4) - 0.055
+            }
+        };
+        Color::new(encode(self.r), encode(self.g), encode(self.b))
+    }
+}
+
+impl Add<Color> for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Color) -> Self::Output {
+        Color::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl AddAssign<Color> for Color {
+    fn add_assign(&mut self, rhs: Color) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Color> for Color {
+    type Output = Color;
+
+    fn sub(self, rhs: Color) -> Self::Output {
+        Color::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+impl Mul<Color> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Self::Output {
+        Color::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
+}
+
+impl Mul<f32> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Color::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl Mul<Color> for f32 {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Self::Output {
+        Color::new(self * rhs.r, self * rhs.g, self * rhs.b)
+    }
+}
+
+impl Div<f32> for Color {
+    type Output = Color;
+
+    fn div(self, rhs: f32) -> Self::Output {
+        Color::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v: f32) -> Color {
+        Color::new(v, v, v)
+    }
+
+    #[test]
+    fn known_values() {
+        assert_eq!(gray(0.5).to_rgb(), Rgb([188, 188, 188]));
+        assert_eq!(gray(0.0).to_rgb(), Rgb([0, 0, 0]));
+        assert_eq!(gray(1.0).to_rgb(), Rgb([255, 255, 255]));
+        assert_eq!(Color::from_rgb(Rgb([0, 0, 0])), gray(0.0));
+        assert_eq!(Color::from_rgb(Rgb([255, 255, 255])), gray(1.0));
+    }
+
+    #[test]
+    fn breakpoint_is_continuous() {
+        // both pieces of the curve meet at 0.04045 in sRGB, 0.0031308 linear
+        let linear = gray(0.04045).srgb_to_linear();
+        assert!((linear.r - 0.04045 / 12.92).abs() < 1e-6);
+        assert!((linear.r - ((0.04045 + 0.055) / 1.055f32).powf(2.4)).abs() < 1e-6);
+        assert!((linear.linear_to_srgb().r - 0.04045).abs() < 1e-5);
+    }
+
+    #[test]
+    fn u8_ramp_round_trips() {
+        for v in 0..=255u8 {
+            let c = Rgb([v, v, v]);
+            assert_eq!(Color::from_rgb(c).to_rgb(), c, "value {}", v);
+        }
+    }
+
+    #[test]
+    fn linear_gradient_encodes_monotonically() {
+        let mut last = 0;
+        for i in 0..=1000 {
+            let Rgb([v, _, _]) = gray(i as f32 / 1000.0).to_rgb();
+            assert!(v >= last, "{} after {} at {}", v, last, i);
+            last = v;
+        }
+        assert_eq!(last, 255);
+    }
+}
@@ -1,13 +1,145 @@
 use crate::*;
 
-#[derive(Clone, Copy, Debug)]
+// Color and roughness parameters are textures evaluated at the hit.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Material {
     Simple {
-        color: Rgb<u8>,
+        color: Texture,
     },
-    Checkerboard {
-        color1: Rgb<u8>,
-        color2: Rgb<u8>,
-        scale: f32,
+    // see Bsdf::Pbr
+    Pbr {
+        color: Texture,
+        metallic: Texture,
+        roughness: Texture,
+        specular: f32,
+        clearcoat: f32,
+        clearcoat_roughness: f32,
     },
+    // see Bsdf::Conductor
+    Conductor {
+        eta: Color,
+        k: Color,
+        roughness: Texture,
+    },
+    Mirror {
+        color: Texture, // tints the reflection
+    },
+    Glass {
+        color: Texture, // tints the transmission
+        ior: f32,
+    },
+    RoughGlass {
+        color: Texture, // tints the transmission
+        ior: f32,
+        roughness: Texture,
+    },
+    Emissive {
+        color: Texture,
+        strength: f32, // emitted radiance is color * strength
+    },
+}
+
+impl Material {
+    pub fn color(&self, info: &Intersection) -> Color {
+        match self {
+            Material::Simple { color }
+            | Material::Pbr { color, .. }
+            | Material::Mirror { color }
+            | Material::Glass { color, .. }
+            | Material::RoughGlass { color, .. }
+            | Material::Emissive { color, .. } => color.eval(info),
+            // reflectance at normal incidence
+            Material::Conductor { eta, k, .. } => fresnel_conductor(1.0, *eta, *k),
+        }
+    }
+
+    // scattering of the materials not traced by the integrators directly, a
+    // diffuse color for the others
+    pub fn bsdf(&self, info: &Intersection) -> Bsdf {
+        let color = self.color(info);
+        match self {
+            Material::Pbr {
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+                ..
+            } => Bsdf::Pbr {
+                color,
+                metallic: metallic.scalar(info),
+                roughness: roughness.scalar(info),
+                specular: *specular,
+                clearcoat: *clearcoat,
+                clearcoat_roughness: *clearcoat_roughness,
+            },
+            Material::Conductor { eta, k, roughness } => Bsdf::Conductor {
+                eta: *eta,
+                k: *k,
+                roughness: roughness.scalar(info),
+            },
+            Material::RoughGlass { ior, roughness, .. } => Bsdf::Dielectric {
+                color,
+                eta: if info.front_face { 1.0 / ior } else { *ior },
+                roughness: roughness.scalar(info),
+            },
+            _ => Bsdf::Diffuse { color },
+        }
+    }
+
+    // light leaves the outside of emissive surfaces only
+    pub fn emission(&self, info: &Intersection) -> Color {
+        match self {
+            Material::Emissive { color, strength } if info.front_face => {
+                color.eval(info) * *strength
+            }
+            _ => Color::black(),
+        }
+    }
+
+    // emission averaged over the surface, for sampling the shape as a light
+    pub fn average_emission(&self) -> Color {
+        match self {
+            Material::Emissive { color, strength } => color.average() * *strength,
+            _ => Color::black(),
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Material::Emissive { .. })
+    }
+}
+
+// complex index of refraction (eta, k) of metals, sampled at red, green and blue
+pub const CONDUCTOR_NAMES: [&str; 4] = ["gold", "copper", "aluminium", "silver"];
+
+pub fn conductor_preset(name: &str) -> Option<(Color, Color)> {
+    match name {
+        "gold" => Some((
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+        )),
+        "copper" => Some((
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+        )),
+        "aluminium" => Some((
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+        )),
+        "silver" => Some((
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+        )),
+        _ => None,
+    }
+}
+
+pub fn ior_preset(name: &str) -> Option<f32> {
+    match name {
+        "water" => Some(1.333),
+        "glass" => Some(1.5),
+        "diamond" => Some(2.42),
+        _ => None,
+    }
 }
@@ -1,3 +1,4 @@
+pub mod color;
 pub mod mat3;
 pub mod mat4;
 pub mod material;
@@ -6,6 +7,7 @@ pub mod quat;
 pub mod vec3;
 pub mod vec4;
 
+pub use color::*;
 pub use mat3::*;
 pub use mat4::*;
 pub use material::*;
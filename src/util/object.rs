@@ -4,25 +4,75 @@ use crate::*;
 pub struct Polygon {
     pub points: Vec<Vec3>,
     pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>, // empty if the obj has no texture coordinates
     pub e1: Vec3,
     pub e2: Vec3,
     pub polygon_normal: Vec3, // not normalized
+    pub tangent: Vec3,        // change of position along u
+    pub bitangent: Vec3,      // change of position along v
+    pub uv_density: f32,
+}
+
+impl Polygon {
+    // sets e1, e2, the normal and the uv derivatives from the points and uvs;
+    // without texture coordinates the uvs are the barycentrics of points 1 and 2
+    pub fn compute_edges(&mut self) {
+        self.e1 = self.points[1] - self.points[0];
+        self.e2 = self.points[2] - self.points[0];
+        self.polygon_normal = self.e1.cross(self.e2);
+        (self.tangent, self.bitangent) = (self.e1, self.e2);
+        self.uv_density = 1.0 / self.polygon_normal.length().sqrt();
+
+        if let [(u0, v0), (u1, v1), (u2, v2)] = self.uvs[..] {
+            let (du1, dv1, du2, dv2) = (u1 - u0, v1 - v0, u2 - u0, v2 - v0);
+            let det = du1 * dv2 - du2 * dv1;
+            if det != 0.0 {
+                self.tangent = (dv2 * self.e1 - dv1 * self.e2) / det;
+                self.bitangent = (du1 * self.e2 - du2 * self.e1) / det;
+                self.uv_density = (det.abs() / self.polygon_normal.length()).sqrt();
+            }
+        }
+    }
+
+    pub fn bounding_box(&self) -> (Vec3, Vec3) {
+        self.points
+            .iter()
+            .fold(empty_box(), |bounding_box, &point| {
+                union(bounding_box, (point, point))
+            })
+    }
 }
 
 #[derive(Clone)]
 pub struct Object {
+    pub name: String,
     pub polygons: Vec<Polygon>,
     pub bounding_box: (Vec3, Vec3), // (min_coordinate, max_coordinate)
+    pub bvh: Bvh,
+}
+
+impl Default for Object {
+    fn default() -> Object {
+        Object::new()
+    }
 }
 
 impl Object {
     pub fn new() -> Object {
         Object {
+            name: String::new(),
             polygons: vec![],
             bounding_box: (
                 Vec3::new(1.0 / 0.0, 1.0 / 0.0, 1.0 / 0.0),
                 Vec3::new(1.0 / -0.0, 1.0 / -0.0, 1.0 / -0.0),
             ),
+            bvh: Bvh::default(),
         }
     }
+
+    pub fn build_bvh(&mut self) {
+        let bounding_boxes: Vec<(Vec3, Vec3)> =
+            self.polygons.iter().map(|p| p.bounding_box()).collect();
+        self.bvh = Bvh::build(&bounding_boxes);
+    }
 }
@@ -8,17 +8,33 @@ pub struct Quat {
     pub w: f32,
 }
 
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+}
+
 impl Quat {
     pub fn new(x: f32, y: f32, z: f32, w: f32) -> Quat {
         Quat { x, y, z, w }
     }
 
-    pub fn default() -> Quat {
-        Quat::new(0.0, 0.0, 0.0, 1.0)
-    }
-
     pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
         let v = axis * (angle * 0.5).sin();
         Quat::new(v.x, v.y, v.z, (angle * 0.5).cos())
     }
+
+    pub fn length(&self) -> f32 {
+        (self.x.powi(2) + self.y.powi(2) + self.z.powi(2) + self.w.powi(2)).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quat {
+        let inv_len = 1.0 / self.length();
+        Quat::new(
+            self.x * inv_len,
+            self.y * inv_len,
+            self.z * inv_len,
+            self.w * inv_len,
+        )
+    }
 }
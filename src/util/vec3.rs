@@ -1,4 +1,4 @@
-use std::ops::{Add, Div, Mul, Sub};
+use std::ops::{Add, Div, Index, Mul, Sub};
 
 use crate::*;
 
@@ -49,7 +49,7 @@ impl Vec3 {
     pub fn normalize(self) -> Vec3 {
         let len = self.length();
         if len == 0.0 {
-            self.clone()
+            self
         } else {
             let inv_len = 1.0 / len;
             self * inv_len
@@ -59,6 +59,27 @@ impl Vec3 {
     pub fn angle(&self, rhs: Vec3) -> f32 {
         (self.dot(rhs) / self.length() / rhs.length()).acos()
     }
+
+    pub fn min(&self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+    }
+
+    pub fn max(&self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+    }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("out of range"),
+        }
+    }
 }
 
 impl Add<Vec3> for Vec3 {
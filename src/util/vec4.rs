@@ -34,7 +34,7 @@ impl Vec4 {
     pub fn normalize(&self) -> Vec4 {
         let len = self.length();
         if len == 0.0 {
-            self.clone()
+            *self
         } else {
             let inv_len = 1.0 / len;
             Vec4 {